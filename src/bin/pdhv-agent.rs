@@ -0,0 +1,42 @@
+use pdhv::{
+    counter_path::CounterPath,
+    source::{self, agent},
+};
+
+use std::{env, net, time};
+
+const DEFAULT_INTERVAL: time::Duration = time::Duration::from_millis(1000);
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+
+    // [--listen address] [--interval milliseconds] counter paths...
+    let mut listen = format!("0.0.0.0:{}", agent::DEFAULT_PORT);
+    let mut interval = DEFAULT_INTERVAL;
+    while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+        match option.as_str() {
+            "--listen" => listen = args.next().expect("--listen needs an address"),
+            "--interval" => {
+                interval = args
+                    .next()
+                    .and_then(|ms| ms.parse().ok())
+                    .filter(|ms| *ms > 0)
+                    .map(time::Duration::from_millis)
+                    .expect("--interval needs milliseconds")
+            }
+            _ => panic!("Unknown option {}", option),
+        }
+    }
+    let paths = args
+        .map(|arg| {
+            CounterPath::parse(&arg).unwrap_or_else(|| panic!("Invalid counter path {}", arg))
+        })
+        .collect::<Vec<_>>();
+
+    let listener = net::TcpListener::bind(&listen).expect("Unable to listen");
+    println!("pdhv-agent listening on {}", listen);
+
+    // shared by every viewer, a single scrape thread or statsd socket each
+    let sources = unsafe { source::default_sources() };
+    agent::serve(listener, sources, paths, interval);
+}
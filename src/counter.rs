@@ -0,0 +1,652 @@
+use random_color::{Luminosity, RandomColor};
+
+use std::{cmp, collections, iter, mem, sync::Arc, time::Duration};
+
+use super::{
+    counter_path::CounterPath,
+    filter::InstanceFilter,
+    history::{self, History},
+    source,
+    unit::{Metadata, Unit},
+};
+
+// the most points in a chart, as many samples are kept and longer histories are charted from
+// a tier
+pub const SAMPLE_COUNT: usize = 600;
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(1000);
+
+pub struct CounterV2 {
+    pub path: CounterPath,
+    pub filter: InstanceFilter,
+    pub metadata: Metadata,
+    // None follows the interval of the query
+    pub interval: Option<Duration>,
+    pub source: usize,
+    pub hcounter: usize,
+    // (timestamp in milliseconds since the unix epoch, value), instances of a sample in a row
+    data: collections::VecDeque<(u64, f64)>,
+    // one per instance, in the order of instance_names
+    histories: Vec<History>,
+
+    // in seconds from the last sample
+    pub interpolated_curves: Arc<Vec<InterpolatedCurve>>,
+    // between two points of the chart, and the deadline the last sample came at
+    pub step: Duration,
+    pub updated: Option<u64>,
+
+    nb_instance: usize,
+    instance_names: Vec<String>,
+
+    pub instance_colors: Vec<[u8; 4]>,
+
+    pub max: [f64; 2],
+    pub avg: [f64; 2],
+}
+
+impl CounterV2 {
+    pub fn new(sources: &mut [Box<dyn source::CounterSource>], path: CounterPath) -> Option<Self> {
+        let (source, hcounter) =
+            sources
+                .iter_mut()
+                .enumerate()
+                .find_map(|(source, counter_source)| {
+                    counter_source
+                        .add_counter(&path)
+                        .map(|hcounter| (source, hcounter))
+                })?;
+
+        Some(Self::with_source(path, source, hcounter))
+    }
+
+    pub fn with_source(path: CounterPath, source: usize, hcounter: usize) -> Self {
+        Self {
+            metadata: Metadata::new(&path),
+            path,
+            filter: InstanceFilter::default(),
+            interval: None,
+            source,
+            hcounter,
+            data: collections::VecDeque::new(),
+            histories: Vec::new(),
+
+            nb_instance: 0,
+            instance_names: Vec::new(),
+
+            interpolated_curves: Arc::new(Vec::new()),
+            step: DEFAULT_INTERVAL,
+            updated: None,
+
+            instance_colors: Vec::new(),
+
+            max: [0.0, 0.0],
+            avg: [0.0, 0.0],
+        }
+    }
+
+    // deadline in milliseconds since the unix epoch, history is the duration charted
+    pub fn update(
+        &mut self,
+        source: Option<&dyn source::CounterSource>,
+        deadline: u64,
+        interval: Duration,
+        history: Duration,
+    ) -> (
+        &CounterPath,
+        Unit,
+        Option<impl iter::Iterator<Item = (&f64, &String)>>,
+    ) {
+        let mut items = source
+            .and_then(|source| source.get_values(self.hcounter))
+            .unwrap_or_default();
+        let timestamp = source
+            .and_then(|source| source.timestamp())
+            .unwrap_or(deadline);
+
+        // instances left out by the filter are neither charted nor logged
+        items.retain(|(name, _)| self.filter.is_match(name));
+        for (_, value) in items.iter_mut() {
+            *value *= self.metadata.scale;
+        }
+
+        if items.is_empty() {
+            return (&self.path, self.metadata.unit, None);
+        }
+
+        // we now have for sure a sample
+
+        if self.instance_names.len() != items.len()
+            || self
+                .instance_names
+                .iter()
+                .zip(items.iter())
+                .any(|(name, (item_name, _))| name != item_name)
+        {
+            // keep the history and color of the instances that are still there
+            let columns = items
+                .iter()
+                .map(|(name, _)| self.instance_names.iter().position(|old| old == name))
+                .collect::<Vec<_>>();
+
+            let nb_sample = match self.nb_instance {
+                0 => 0,
+                nb_instance => self.data.len() / nb_instance,
+            };
+
+            self.data = (0..nb_sample)
+                .flat_map(|sample| columns.iter().map(move |column| (sample, *column)))
+                .map(|(sample, column)| {
+                    let row = sample * self.nb_instance;
+                    (
+                        self.data[row].0,
+                        column.map_or(0.0, |column| self.data[row + column].1),
+                    )
+                })
+                .collect();
+            self.interpolated_curves = Arc::new(Vec::new());
+
+            let mut histories = mem::take(&mut self.histories)
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>();
+            self.histories = columns
+                .iter()
+                .map(|column| {
+                    column
+                        .and_then(|column| histories[column].take())
+                        .unwrap_or_default()
+                })
+                .collect();
+
+            self.instance_colors = columns
+                .iter()
+                .map(|column| match column {
+                    Some(column) => self.instance_colors[*column],
+                    None => {
+                        let tmp = RandomColor::new()
+                            .luminosity(Luminosity::Light)
+                            .to_rgb_array();
+
+                        [tmp[0], tmp[1], tmp[2], 255]
+                    }
+                })
+                .collect();
+
+            self.nb_instance = items.len();
+            self.instance_names = items.iter().map(|(name, _)| name.clone()).collect();
+
+            for history in self.histories.iter_mut() {
+                history.cap(self.nb_instance);
+            }
+        }
+
+        // a replay seeking backward starts over, a paused one gives the same sample again
+        let last = self.data.back().map(|(last, _)| *last);
+        if last.is_some_and(|last| timestamp < last) {
+            self.data.clear();
+            self.histories = (0..self.nb_instance)
+                .map(|_| History::new(self.nb_instance))
+                .collect();
+        }
+
+        if last != Some(timestamp) {
+            self.data
+                .extend(items.iter().map(|(_, value)| (timestamp, *value)));
+            drop(self.data.drain(
+                0..cmp::max(
+                    0,
+                    self.data.len() as isize - (self.nb_instance * SAMPLE_COUNT) as isize,
+                ) as usize,
+            ));
+
+            for (history, (_, value)) in self.histories.iter_mut().zip(items.iter()) {
+                history.push(timestamp, *value);
+            }
+
+            self.updated = Some(deadline);
+            self.chart(interval, history);
+        }
+
+        (
+            &self.path,
+            self.metadata.unit,
+            Some(
+                self.data
+                    .range((self.data.len() - self.nb_instance)..self.data.len())
+                    .map(|(_, value)| value)
+                    .zip(self.instance_names.iter()),
+            ),
+        )
+    }
+
+    // the samples when the history fits in the chart, the averages of a tier otherwise
+    pub fn chart(&mut self, interval: Duration, history: Duration) {
+        let last = match self.data.back() {
+            Some((last, _)) => *last,
+            None => return,
+        };
+        let since = last.saturating_sub(history.as_millis() as u64);
+
+        let points = if history.as_millis() / interval.as_millis().max(1) <= SAMPLE_COUNT as u128 {
+            self.step = interval;
+
+            (0..self.nb_instance)
+                .map(|of| {
+                    self.data
+                        .iter()
+                        .skip(of)
+                        .step_by(self.nb_instance)
+                        .filter(|(timestamp, _)| *timestamp >= since)
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        } else {
+            let (tier, step) = history::tier_for(history, SAMPLE_COUNT);
+            self.step = step;
+
+            self.histories
+                .iter()
+                .map(|instance| instance.tiers[tier].averages(since, step))
+                .collect::<Vec<_>>()
+        };
+
+        self.interpolated_curves = Arc::new(
+            points
+                .iter()
+                .map(|values| {
+                    InterpolatedCurve::new(values.iter().map(|(timestamp, value)| {
+                        (
+                            (*timestamp as f64 - last as f64) as f32 / 1000.0,
+                            *value as f32,
+                        )
+                    }))
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        // the highest instance over time, its max and its average weighted by duration
+        let mut highest = collections::BTreeMap::<u64, f64>::new();
+        for (timestamp, value) in points.iter().flatten() {
+            let max = highest.entry(*timestamp).or_insert(f64::MIN);
+            *max = max.max(*value);
+        }
+
+        let (first, last) = match (highest.keys().next(), highest.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return,
+        };
+        let avg = match last - first {
+            0 => highest.values().sum::<f64>() / highest.len() as f64,
+            span => {
+                highest
+                    .iter()
+                    .zip(highest.iter().skip(1))
+                    .map(|((t0, v0), (t1, v1))| (v0 + v1) / 2.0 * (t1 - t0) as f64)
+                    .sum::<f64>()
+                    / span as f64
+            }
+        };
+
+        self.max[0] = self.max[1];
+        self.max[1] = highest.values().copied().fold(f64::MIN, f64::max);
+        self.avg[0] = self.avg[1];
+        self.avg[1] = avg;
+    }
+
+    pub fn label(&self) -> String {
+        self.metadata
+            .name
+            .clone()
+            .unwrap_or_else(|| self.path.to_string())
+    }
+
+    pub fn last_values(&self) -> impl iter::Iterator<Item = (&String, f64)> {
+        self.instance_names.iter().zip(
+            self.data
+                .range(self.data.len().saturating_sub(self.nb_instance)..)
+                .map(|(_, value)| *value),
+        )
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub fn get_data_by_instance(
+        &self,
+    ) -> Option<impl iter::Iterator<Item = impl iter::Iterator<Item = &(u64, f64)>>> {
+        if !self.data.is_empty() {
+            Some(
+                (0..self.nb_instance)
+                    .map(|offset| self.data.iter().skip(offset).step_by(self.nb_instance)),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+pub struct InterpolatedCurve {
+    pub n: usize,
+
+    x: Vec<f32>,
+    a: Vec<f32>,
+    b: Vec<f32>,
+    c: Vec<f32>,
+    d: Vec<f32>,
+}
+
+impl InterpolatedCurve {
+    // a natural cubic spline through the (x, y) points, x increasing
+    pub fn new(points: impl iter::Iterator<Item = (f32, f32)>) -> Self {
+        let (x, a): (Vec<f32>, Vec<f32>) = points.unzip();
+        let n = a.len().saturating_sub(1);
+        let h = (0..n).map(|i| x[i + 1] - x[i]).collect::<Vec<_>>();
+
+        let mut l = vec![1.0_f32];
+        let mut u = vec![0.0_f32];
+        let mut z = vec![0.0_f32];
+
+        for i in 1..n {
+            l.push(2.0 * (x[i + 1] - x[i - 1]) - h[i - 1] * u[i - 1]);
+            u.push(h[i] / l[i]);
+            z.push(
+                ((3.0 / h[i] * (a[i + 1] - a[i]) - 3.0 / h[i - 1] * (a[i] - a[i - 1]))
+                    - h[i - 1] * z[i - 1])
+                    / l[i],
+            );
+        }
+        l.push(1.0);
+        z.push(0.0);
+
+        let mut c = vec![0.0_f32; n + 1];
+        let mut b = vec![0.0_f32; n];
+        let mut d = vec![0.0_f32; n];
+
+        for j in (0..n).rev() {
+            c[j] = z[j] - u[j] * c[j + 1];
+            b[j] = (a[j + 1] - a[j]) / h[j] - h[j] * (c[j + 1] + 2.0 * c[j]) / 3.0;
+            d[j] = (c[j + 1] - c[j]) / (3.0 * h[j]);
+        }
+
+        Self { n, x, a, b, c, d }
+    }
+
+    pub fn start(&self) -> Option<f32> {
+        self.x.first().copied()
+    }
+
+    // the first and last segments go on past the ends
+    pub fn interpolate(&self, x: f32) -> f32 {
+        let j = self.x[..self.n]
+            .partition_point(|start| *start <= x)
+            .saturating_sub(1);
+        let dx = x - self.x[j];
+
+        self.a[j] + self.b[j] * dx + self.c[j] * dx.powf(2.0) + self.d[j] * dx.powf(3.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time;
+
+    // gives whatever the test put in it
+    #[derive(Default)]
+    struct Fake {
+        values: Vec<(String, f64)>,
+        timestamp: Option<u64>,
+    }
+
+    impl source::CounterSource for Fake {
+        fn add_counter(&mut self, _path: &CounterPath) -> Option<usize> {
+            Some(0)
+        }
+
+        fn remove_counter(&mut self, _hcounter: usize) {}
+
+        fn collect(&mut self, _elapsed: time::Duration) -> bool {
+            true
+        }
+
+        fn get_values(&self, _hcounter: usize) -> Option<Vec<(String, f64)>> {
+            Some(self.values.clone())
+        }
+
+        fn timestamp(&self) -> Option<u64> {
+            self.timestamp
+        }
+    }
+
+    fn counter() -> CounterV2 {
+        CounterV2::with_source(CounterPath::parse(r"\Fake\Value").unwrap(), 0, 0)
+    }
+
+    fn update(counter: &mut CounterV2, fake: &Fake, deadline: u64) -> Option<Vec<(String, f64)>> {
+        let (_, _, values) = counter.update(
+            Some(fake),
+            deadline,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+        );
+
+        values.map(|values| values.map(|(value, name)| (name.clone(), *value)).collect())
+    }
+
+    #[test]
+    fn update_returns_the_last_values() {
+        let mut counter = counter();
+        let fake = Fake {
+            values: vec![("a".to_string(), 1.0), ("b".to_string(), 2.0)],
+            ..Default::default()
+        };
+
+        assert_eq!(update(&mut counter, &fake, 1000), Some(fake.values.clone()));
+        assert_eq!(counter.updated, Some(1000));
+        assert_eq!(counter.interpolated_curves.len(), 2);
+    }
+
+    #[test]
+    fn update_without_values_is_no_data() {
+        let mut counter = counter();
+
+        assert_eq!(update(&mut counter, &Fake::default(), 1000), None);
+        assert_eq!(counter.updated, None);
+    }
+
+    #[test]
+    fn max_and_average_weighted_by_duration() {
+        let mut counter = counter();
+        let mut fake = Fake::default();
+
+        // the highest instance is b, then a
+        for (deadline, a, b) in [(1000, 1.0, 3.0), (2000, 2.0, 1.0), (4000, 5.0, 0.0)] {
+            fake.values = vec![("a".to_string(), a), ("b".to_string(), b)];
+            update(&mut counter, &fake, deadline);
+        }
+
+        // 3, 2, 5 over 1 s then 2 s
+        assert_eq!(counter.max[1], 5.0);
+        assert_eq!(counter.avg[1], (2.5 * 1000.0 + 3.5 * 2000.0) / 3000.0);
+        // the previous collection, to animate from
+        assert_eq!(counter.max[0], 3.0);
+        assert_eq!(counter.avg[0], 2.5);
+    }
+
+    #[test]
+    fn instances_keep_their_color_and_samples() {
+        let mut counter = counter();
+        let mut fake = Fake {
+            values: vec![("a".to_string(), 1.0), ("b".to_string(), 2.0)],
+            ..Default::default()
+        };
+        update(&mut counter, &fake, 1000);
+        let color_b = counter.instance_colors[1];
+
+        fake.values = vec![("b".to_string(), 3.0), ("c".to_string(), 4.0)];
+        update(&mut counter, &fake, 2000);
+
+        assert_eq!(counter.instance_colors[0], color_b);
+        let samples = counter
+            .get_data_by_instance()
+            .unwrap()
+            .map(|instance| instance.copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        // c was not there yet, it starts at zero
+        assert_eq!(
+            samples,
+            vec![
+                vec![(1000, 2.0), (2000, 3.0)],
+                vec![(1000, 0.0), (2000, 4.0)]
+            ]
+        );
+    }
+
+    #[test]
+    fn source_timestamps_are_used_once() {
+        let mut counter = counter();
+        let mut fake = Fake {
+            values: vec![("a".to_string(), 1.0)],
+            timestamp: Some(5000),
+        };
+        update(&mut counter, &fake, 1000);
+
+        // a paused replay gives the same sample again
+        fake.values[0].1 = 2.0;
+        update(&mut counter, &fake, 2000);
+        assert_eq!(counter.updated, Some(1000));
+        assert_eq!(counter.last_values().next().unwrap().1, 1.0);
+
+        // seeking backward starts over
+        fake.timestamp = Some(3000);
+        update(&mut counter, &fake, 3000);
+        let samples = counter.get_data_by_instance().unwrap().next().unwrap();
+        assert_eq!(samples.copied().collect::<Vec<_>>(), vec![(3000, 2.0)]);
+    }
+
+    #[test]
+    fn short_intervals_are_charted_from_samples() {
+        let mut counter = counter();
+        let fake = Fake {
+            values: vec![("a".to_string(), 1.0)],
+            ..Default::default()
+        };
+        for deadline in (0..100).map(|sample| 1000 + sample * 100) {
+            update(&mut counter, &fake, deadline);
+        }
+
+        counter.chart(Duration::from_millis(100), Duration::from_secs(60));
+        assert_eq!(counter.step, Duration::from_millis(100));
+        assert_eq!(counter.interpolated_curves[0].n, 99);
+
+        counter.chart(
+            Duration::from_millis(100),
+            Duration::from_secs(7 * 24 * 3600),
+        );
+        assert_eq!(counter.step, Duration::from_secs(17 * 60));
+    }
+
+    // collected and updated once a second, as the query does
+    fn synthetic(
+        synthetic: source::synthetic::Synthetic,
+        path: &str,
+        ticks: u64,
+    ) -> (CounterV2, Vec<Vec<(String, f64)>>) {
+        let mut sources: Vec<Box<dyn source::CounterSource>> = vec![Box::new(synthetic)];
+        let mut counter = CounterV2::new(&mut sources, CounterPath::parse(path).unwrap()).unwrap();
+
+        let mut samples = Vec::new();
+        for tick in 1..=ticks {
+            sources[0].collect(Duration::from_secs(1));
+            let (_, _, values) = counter.update(
+                Some(sources[0].as_ref()),
+                tick * 1000,
+                Duration::from_secs(1),
+                Duration::from_secs(60),
+            );
+            samples.push(
+                values
+                    .unwrap()
+                    .map(|(value, name)| (name.clone(), *value))
+                    .collect(),
+            );
+        }
+
+        (counter, samples)
+    }
+
+    #[test]
+    fn square_wave_max_and_average() {
+        let (counter, _) = synthetic(
+            source::synthetic::Synthetic::new(1, None, 1),
+            r"\Synthetic(*)\Square",
+            20,
+        );
+
+        // 0 for 9 s, 100 for 9 s and a ramp each way in between
+        assert_eq!(counter.max[1], 100.0);
+        assert_eq!(counter.avg[1], 1000.0 / 19.0);
+    }
+
+    #[test]
+    fn same_seed_same_statistics() {
+        let path = r"\Synthetic(*)\Random Walk";
+        let (counter, samples) =
+            synthetic(source::synthetic::Synthetic::new(3, None, 42), path, 30);
+        let (other, other_samples) =
+            synthetic(source::synthetic::Synthetic::new(3, None, 42), path, 30);
+
+        assert_eq!(samples, other_samples);
+        assert_eq!((counter.max, counter.avg), (other.max, other.avg));
+
+        // the highest instance of every sample
+        let max = samples
+            .iter()
+            .flatten()
+            .map(|(_, value)| *value)
+            .fold(f64::MIN, f64::max);
+        assert_eq!(counter.max[1], max);
+        assert!((0.0..=100.0).contains(&counter.avg[1]));
+
+        let (_, seeded) = synthetic(source::synthetic::Synthetic::new(3, None, 7), path, 30);
+        assert_ne!(samples, seeded);
+    }
+
+    #[test]
+    fn churned_instances_start_at_zero() {
+        let (counter, samples) = synthetic(
+            source::synthetic::Synthetic::new(2, Some(5), 1),
+            r"\Synthetic(*)\Sine",
+            5,
+        );
+
+        let names = |sample: &Vec<(String, f64)>| {
+            sample
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&samples[3]), vec!["0", "1"]);
+        assert_eq!(names(&samples[4]), vec!["1", "2"]);
+        assert_eq!(counter.instance_colors.len(), 2);
+
+        // the new instance starts at zero
+        let first = counter
+            .get_data_by_instance()
+            .unwrap()
+            .map(|instance| instance.copied().next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(first[1], (1000, 0.0));
+    }
+
+    #[test]
+    fn curve_goes_through_the_points() {
+        let points = [(-3.0, 1.0), (-2.0, 4.0), (-0.5, 2.0), (0.0, 3.0)];
+        let curve = InterpolatedCurve::new(points.iter().copied());
+
+        assert_eq!(curve.n, 3);
+        assert_eq!(curve.start(), Some(-3.0));
+        for (x, y) in points {
+            assert!((curve.interpolate(x) - y).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use std::{fmt, iter};
+
+// \\machine\object(parent/instance#index)\counter, only the object and the counter are required
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CounterPath {
+    pub machine: Option<String>,
+    pub object: String,
+    pub parent: Option<String>,
+    pub instance: Option<String>,
+    pub index: Option<u32>,
+    pub counter: String,
+}
+
+impl CounterPath {
+    pub fn parse(path: &str) -> Option<Self> {
+        let path = path.trim_end_matches(char::from(0)).strip_prefix('\\')?;
+        let (machine, path) = match path.strip_prefix('\\') {
+            Some(path) => {
+                let (machine, path) = path.split_once('\\')?;
+                (Some(machine.to_string()), path)
+            }
+            None => (None, path),
+        };
+
+        let (object, counter) = path.rsplit_once('\\')?;
+        let (object, instance) = match object.split_once('(') {
+            Some((object, instance)) => (object, Some(instance.strip_suffix(')')?)),
+            None => (object, None),
+        };
+
+        if object.is_empty() || counter.is_empty() {
+            return None;
+        }
+
+        // the first '/' splits off the parent, as pdh does, so kworker/0:1 or a cgroup path get one
+        // too. the other sources only look at full_instance, which puts the parts back together
+        let (parent, instance) = match instance.and_then(|instance| instance.split_once('/')) {
+            Some((parent, instance)) => (Some(parent.to_string()), Some(instance)),
+            None => (None, instance),
+        };
+
+        // only a trailing number is an index, a '#' elsewhere is part of the name
+        let (instance, index) = match instance.and_then(|instance| instance.rsplit_once('#')) {
+            Some((name, index)) if index.parse::<u32>().is_ok() => (Some(name), index.parse().ok()),
+            _ => (instance, None),
+        };
+
+        Some(Self {
+            machine,
+            object: object.to_string(),
+            parent,
+            instance: instance.map(String::from),
+            index,
+            counter: counter.to_string(),
+        })
+    }
+
+    pub fn from_wide(path: &[u16]) -> Option<Self> {
+        Self::parse(&String::from_utf16_lossy(path))
+    }
+
+    // nul terminated, for the win32 api
+    pub fn to_wide(&self) -> Vec<u16> {
+        self.to_string()
+            .encode_utf16()
+            .chain(iter::once(0))
+            .collect()
+    }
+
+    // parent/instance#index, as the sources name their instances
+    pub fn full_instance(&self) -> Option<String> {
+        let mut full_instance = self.instance.clone()?;
+
+        if let Some(parent) = &self.parent {
+            full_instance = format!("{}/{}", parent, full_instance);
+        }
+        if let Some(index) = self.index {
+            full_instance = format!("{}#{}", full_instance, index);
+        }
+
+        Some(full_instance)
+    }
+
+    pub fn with_machine(&self, machine: Option<&str>) -> Self {
+        Self {
+            machine: machine.map(String::from),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for CounterPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(machine) = &self.machine {
+            write!(f, "\\\\{}", machine)?;
+        }
+
+        write!(f, "\\{}", self.object)?;
+
+        if let Some(full_instance) = self.full_instance() {
+            write!(f, "({})", full_instance)?;
+        }
+
+        write!(f, "\\{}", self.counter)
+    }
+}
+
+impl Serialize for CounterPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// older saves hold nul terminated utf-16 arrays
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    Text(String),
+    Wide(Vec<u16>),
+}
+
+impl<'de> Deserialize<'de> for CounterPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = match Saved::deserialize(deserializer)? {
+            Saved::Text(path) => path,
+            Saved::Wide(path) => String::from_utf16_lossy(&path),
+        };
+
+        Self::parse(&path)
+            .ok_or_else(|| de::Error::custom(format!("invalid counter path {}", path)))
+    }
+}
+
+// '*' matches any run of characters, like the instance wildcards of pdh
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => match name.strip_prefix(prefix) {
+            Some(name) => (0..=name.len())
+                .filter(|start| name.is_char_boundary(*start))
+                .any(|start| matches(rest, &name[start..])),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_round_trip() {
+        for path in [
+            r"\\machine\Processor(_Total)\% Processor Time",
+            r"\Thread(chrome/12#3)\Context Switches/sec",
+            r"\Process(C# #fast)\Working Set",
+            r"\Memory\Available Bytes",
+        ] {
+            assert_eq!(CounterPath::parse(path).unwrap().to_string(), path);
+        }
+
+        let path =
+            CounterPath::parse(r"\\machine\Thread(chrome/12#3)\Context Switches/sec").unwrap();
+        assert_eq!(path.machine.as_deref(), Some("machine"));
+        assert_eq!(path.object, "Thread");
+        assert_eq!(path.parent.as_deref(), Some("chrome"));
+        assert_eq!(path.instance.as_deref(), Some("12"));
+        assert_eq!(path.index, Some(3));
+        assert_eq!(path.counter, "Context Switches/sec");
+
+        // only a number is an index
+        let path = CounterPath::parse(r"\Process(C# #fast)\Working Set").unwrap();
+        assert_eq!(path.instance.as_deref(), Some("C# #fast"));
+        assert_eq!(path.index, None);
+
+        for path in [
+            r"Memory\Available Bytes",
+            r"\Memory",
+            r"\(x)\y",
+            r"\Process(x\y",
+        ] {
+            assert!(CounterPath::parse(path).is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn slashes_split_off_a_parent() {
+        // the instance is split as if it were pdh, but comes back whole
+        for (path, instance) in [
+            (
+                r"\Process(kworker/0:1#12)\% Processor Time",
+                "kworker/0:1#12",
+            ),
+            (
+                r"\Cgroup(system.slice/cron.service)\Memory",
+                "system.slice/cron.service",
+            ),
+            (r"\\localhost:9100\up(a/b/c)\up", "a/b/c"),
+        ] {
+            let path = CounterPath::parse(path).unwrap();
+            assert_eq!(path.full_instance().as_deref(), Some(instance));
+        }
+
+        let path = CounterPath::parse(r"\Process(kworker/0:1#12)\% Processor Time").unwrap();
+        assert_eq!(path.parent.as_deref(), Some("kworker"));
+        assert_eq!(path.instance.as_deref(), Some("0:1"));
+        assert_eq!(path.index, Some(12));
+    }
+
+    #[test]
+    fn saves_load_as_text_or_legacy_wide_strings() {
+        let path = CounterPath::parse(r"\Processor(_Total)\% Processor Time").unwrap();
+
+        let text = serde_json::to_string(&path).unwrap();
+        assert_eq!(serde_json::from_str::<CounterPath>(&text).unwrap(), path);
+
+        let wide = serde_json::to_string(&path.to_wide()).unwrap();
+        assert_eq!(serde_json::from_str::<CounterPath>(&wide).unwrap(), path);
+
+        assert!(serde_json::from_str::<CounterPath>(r#""not a path""#).is_err());
+    }
+
+    #[test]
+    fn globs_match() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("chrome", "chrome"));
+        assert!(!matches("chrome", "chromium"));
+        assert!(matches("chrom*", "chromium"));
+        assert!(matches("*.service", "cron.service"));
+        assert!(!matches("*.service", "cron.slice"));
+        assert!(matches("a*b*c", "a-b-b-c"));
+        assert!(!matches("a*b*c", "a-c-b"));
+        assert!(matches("é*", "été"));
+    }
+}
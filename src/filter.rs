@@ -0,0 +1,146 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::counter_path;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedPattern", into = "SavedPattern")]
+pub enum Pattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            Self::Glob(glob) => counter_path::matches(glob, name),
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+// "w3wp*" is a glob, {"regex": "^w3wp#\\d+$"} a regex
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedPattern {
+    Glob(String),
+    Tagged(TaggedPattern),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TaggedPattern {
+    Glob(String),
+    Regex(String),
+}
+
+impl TryFrom<SavedPattern> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(saved: SavedPattern) -> Result<Self, Self::Error> {
+        match saved {
+            SavedPattern::Glob(glob) | SavedPattern::Tagged(TaggedPattern::Glob(glob)) => {
+                Ok(Self::Glob(glob))
+            }
+            SavedPattern::Tagged(TaggedPattern::Regex(regex)) => {
+                Ok(Self::Regex(Regex::new(&regex)?))
+            }
+        }
+    }
+}
+
+impl From<Pattern> for SavedPattern {
+    fn from(pattern: Pattern) -> Self {
+        match pattern {
+            Pattern::Glob(glob) => Self::Glob(glob),
+            Pattern::Regex(regex) => Self::Tagged(TaggedPattern::Regex(regex.as_str().to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct InstanceFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Pattern>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<Pattern>,
+}
+
+impl InstanceFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // without include patterns every instance is included
+    pub fn is_match(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(name)))
+            && !self.exclude.iter().any(|pattern| pattern.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(json: &str) -> InstanceFilter {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn patterns_are_globs_or_regexes() {
+        let filter =
+            filter(r#"{"include": ["w3wp*", {"glob": "*.service"}, {"regex": "^chrome#\\d+$"}]}"#);
+
+        for name in ["w3wp", "w3wp#2", "cron.service", "chrome#12"] {
+            assert!(filter.is_match(name), "{}", name);
+        }
+        for name in ["iis", "cron.slice", "chrome", "chrome#x", "xchrome#1"] {
+            assert!(!filter.is_match(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        assert!(filter("{}").is_empty());
+        assert!(filter("{}").is_match("anything"));
+
+        let filter = filter(r#"{"include": ["chrome*"], "exclude": ["*#0", {"regex": "crash"}]}"#);
+        assert!(filter.is_match("chrome#1"));
+        assert!(!filter.is_match("chrome#0"));
+        assert!(!filter.is_match("chrome_crashpad"));
+        assert!(!filter.is_match("firefox"));
+
+        // without include patterns only the excluded are dropped
+        let filter = self::filter(r#"{"exclude": ["_Total"]}"#);
+        assert!(filter.is_match("0"));
+        assert!(!filter.is_match("_Total"));
+    }
+
+    #[test]
+    fn filters_round_trip() {
+        let json = r#"{"include":["w3wp*",{"regex":"^chrome#\\d+$"}],"exclude":["*#0"]}"#;
+        assert_eq!(serde_json::to_string(&filter(json)).unwrap(), json);
+
+        // tagged globs are saved plain, empty lists not at all
+        assert_eq!(
+            serde_json::to_string(&filter(r#"{"include": [{"glob": "a*"}], "exclude": []}"#))
+                .unwrap(),
+            r#"{"include":["a*"]}"#
+        );
+    }
+
+    #[test]
+    fn bad_regexes_only_fail_their_own_filter() {
+        // the saved counters are loaded one at a time, like this
+        let saved: Vec<serde_json::Value> = serde_json::from_str(
+            r#"[{"include": ["a*"]}, {"include": [{"regex": "("}]}, {"exclude": ["b"]}]"#,
+        )
+        .unwrap();
+
+        let loaded: Vec<bool> = saved
+            .into_iter()
+            .map(|saved| serde_json::from_value::<InstanceFilter>(saved).is_ok())
+            .collect();
+        assert_eq!(loaded, [true, false, true]);
+    }
+}
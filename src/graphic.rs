@@ -1,7 +1,7 @@
 use image::GenericImageView;
 use wgpu::util::DeviceExt;
 
-use std::{cmp, f32::consts::PI, mem, ops, time};
+use std::{cmp, f32::consts::PI, mem, ops};
 
 use super::{query, window};
 
@@ -585,18 +585,18 @@ impl Graphic {
         Ok(())
     }
 
-    pub fn update(&mut self, query_v2: &query::QueryV2, dpi: u32, cx: i32, cy: i32) {
+    pub fn update(&mut self, snapshot: &query::Snapshot, dpi: u32, cx: i32, cy: i32) {
         let bounds = Pt {
             x: self.config.width as f32,
             y: self.config.height as f32,
         };
         let scale = dpi as f32 / BASE_DPI;
 
-        let nb_box = query_v2.counters.len();
+        let nb_box = snapshot.counters.len();
         let nb_box_x = cmp::max(
             1,
             cmp::min(
-                query_v2.counters.len(),
+                snapshot.counters.len(),
                 bounds.x as usize / (MIN_BOX_WIDTH as f32 * (dpi as f32 / BASE_DPI)) as usize,
             ),
         );
@@ -606,7 +606,6 @@ impl Graphic {
         let pa48 = 96.0 * scale;
         let pa44 = 88.0 * scale;
         let pa32 = 64.0 * scale;
-        let pa26 = 52.0 * scale;
         let pa20 = 40.0 * scale;
         let pa18 = 36.0 * scale;
         let pa16 = 32.0 * scale;
@@ -621,7 +620,7 @@ impl Graphic {
         let mut vrt: Vec<Vertex> = Vec::new();
         let mut vrtx: Vec<TextureVertex> = Vec::new();
 
-        for (index, counter) in query_v2.counters.values().enumerate() {
+        for (index, counter) in snapshot.counters.iter().enumerate() {
             let (px, py) = (
                 index.rem_euclid(nb_box_x) as f32,
                 index.div_euclid(nb_box_x) as f32,
@@ -726,20 +725,21 @@ impl Graphic {
                 TX15.1
             ));
 
-            let of = time::Instant::now()
-                .duration_since(query_v2.last_update)
-                .as_secs_f32();
+            // fraction of a step of the chart since its last point
+            let of = (counter.elapsed().as_secs_f32() / counter.step.as_secs_f32()).min(1.0);
 
             let old_range = f64_max(1.0, f64_max(counter.max[0], counter.avg[0] * 2.0));
             let new_range = f64_max(1.0, f64_max(counter.max[1], counter.avg[1] * 2.0));
 
             let range = old_range as f32 + (new_range - old_range) as f32 * of;
 
+            let unit = counter.unit;
+
             self.glyph_brush.queue(wgpu_glyph::Section {
                 screen_position: (p6.x - pa32 + pa13, bounds.y - (p2.y - pa6)),
-                bounds: (pa26, pa20),
+                bounds: (pa44, pa20),
                 text: vec![
-                    wgpu_glyph::Text::new(&range.round().to_string()).with_scale(20.0 * scale)
+                    wgpu_glyph::Text::new(&unit.format(range as f64)).with_scale(20.0 * scale)
                 ],
                 layout: wgpu_glyph::Layout::default()
                     .line_breaker(wgpu_glyph::BuiltInLineBreaker::AnyCharLineBreaker)
@@ -748,8 +748,8 @@ impl Graphic {
 
             self.glyph_brush.queue(wgpu_glyph::Section {
                 screen_position: (p6.x - pa32 + pa13, bounds.y - (p6.y + pa5)),
-                bounds: (pa26, pa20),
-                text: vec![wgpu_glyph::Text::new(&(range / 2.0).round().to_string())
+                bounds: (pa44, pa20),
+                text: vec![wgpu_glyph::Text::new(&unit.format(range as f64 / 2.0))
                     .with_scale(20.0 * scale)],
                 layout: wgpu_glyph::Layout::default()
                     .line_breaker(wgpu_glyph::BuiltInLineBreaker::AnyCharLineBreaker)
@@ -765,7 +765,11 @@ impl Graphic {
                     screen_position: (p3.x + pa18, bounds.y - (p3.y - pa18)),
                     bounds: (p6.x - pa6 - pa44 - p5.x - pa18, p2.y - pa18 - p1.y - pa18),
                     text: vec![wgpu_glyph::Text::new(
-                        &String::from_utf16(counter.path.as_slice()).unwrap(),
+                        &counter
+                            .last_values
+                            .iter()
+                            .map(|(name, value)| format!("\n{} {}", name, unit.format(*value)))
+                            .fold(counter.label.clone(), |text, line| text + &line),
                     )
                     .with_scale(20.0 * scale)],
                     layout: wgpu_glyph::Layout::default(),
@@ -774,31 +778,35 @@ impl Graphic {
 
             //vrt.extend_from_slice(&quad!(p1 + (pa6, pa18), p2 + (-pa44, -pa18), bounds, [255, 255, 0, 255]));
 
+            // in seconds from the last sample, the chart scrolls up to a step past it
+            let history = snapshot.history.as_secs_f32();
+            let end = of * counter.step.as_secs_f32();
+            let width = p6.x - pa6 - pa44 - p5.x;
+
             for (ic, color) in counter
                 .interpolated_curves
                 .iter()
                 .zip(counter.instance_colors.iter())
             {
-                let step = (p6.x - pa6 - pa44 - p5.x) / (ic.n - 1) as f32; 
-                let mut st = of;
+                let start = match ic.start() {
+                    Some(start) if ic.n > 0 => start.max(end - history),
+                    _ => continue,
+                };
+                let segments = ic.n * ACC;
+                let dx = (end - start) / segments as f32;
 
-                let mut a1 = Pt { 
-                    x: p1.x + pa6 + st * step - of * step,
-                    y: p1.y + pa18 + (ic.interpolate(st, cmp::min(ic.n - 1, st.floor() as _)) / range) * (p2.y - pa18 - p1.y - pa18),
+                let point = |x: f32| Pt {
+                    x: p1.x + pa6 + (x - end + history) / history * width,
+                    y: p1.y + pa18 + (ic.interpolate(x) / range) * (p2.y - pa18 - p1.y - pa18),
                 };
-                let mut a2 = a1 + (0.0, pa2and5);
 
-                st += 1.0 / ACC as f32;
+                let mut a1 = point(start);
+                let mut a2 = a1 + (0.0, pa2and5);
 
-                while st < ((ic.n - 1) as f32 + of - 0.1) {
-                    let b1 = Pt { 
-                        x: p1.x + pa6 + st * step - of * step,
-                        y: p1.y + pa18 + (ic.interpolate(st, cmp::min(ic.n - 1, st.floor() as _)) / range) * (p2.y - pa18 - p1.y - pa18),
-                    };
-                    let c1 = Pt { 
-                        x: p1.x + pa6 + (st + 1.0 / ACC as f32) * step - of * step,
-                        y: p1.y + pa18 + (ic.interpolate(st, cmp::min(ic.n - 1, (st + 1.0 / ACC as f32).floor() as _)) / range) * (p2.y - pa18 - p1.y - pa18),
-                    };
+                for segment in 1..segments {
+                    let x = start + dx * segment as f32;
+                    let b1 = point(x);
+                    let c1 = point(x + dx);
 
                     let ac = Pt { x: c1.x - a1.x, y: c1.y - a1.y };
                     let an = f32::atan(ac.y / ac.x) + PI / 2.0;
@@ -816,13 +824,9 @@ impl Graphic {
 
                     a1 = b1;
                     a2 = b2;
-                    st += 1.0 / ACC as f32;
                 }
 
-                let b1 = Pt { 
-                    x: p1.x + pa6 + st * step - of * step,
-                    y: p1.y + pa18 + (ic.interpolate(st, cmp::min(ic.n - 1, st.floor() as _)) / range) * (p2.y - pa18 - p1.y - pa18),
-                };
+                let b1 = point(end);
                 let b2 = b1 + (0.0, pa2and5);
 
                 vrt.extend_from_slice(&quad!(
@@ -0,0 +1,241 @@
+use std::{collections, time::Duration};
+
+const HOUR: u64 = 60 * 60;
+
+// (resolution, retention) of the tiers, from the finest
+pub const TIERS: [(Duration, Duration); 3] = [
+    (Duration::from_secs(1), Duration::from_secs(HOUR)),
+    (Duration::from_secs(10), Duration::from_secs(24 * HOUR)),
+    (Duration::from_secs(60), Duration::from_secs(7 * 24 * HOUR)),
+];
+
+// of each tier, shared by the instances of a counter, at most about 2.6 MB
+const MAX_BUCKETS: usize = 1 << 16;
+
+// the samples that fell in a bucket
+#[derive(Clone, Copy)]
+pub struct Bucket {
+    // in milliseconds since the unix epoch
+    pub start: u64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    count: u32,
+}
+
+pub struct Tier {
+    pub resolution: Duration,
+    retention: Duration,
+    // the buckets kept, whatever the retention
+    capacity: usize,
+    buckets: collections::VecDeque<Bucket>,
+}
+
+impl Tier {
+    fn push(&mut self, timestamp: u64, value: f64) {
+        let resolution = self.resolution.as_millis() as u64;
+        let start = timestamp - timestamp % resolution;
+
+        match self.buckets.back_mut() {
+            // a clock going backward lands in the last bucket, they stay in order
+            Some(bucket) if bucket.start >= start => {
+                bucket.count += 1;
+                bucket.min = bucket.min.min(value);
+                bucket.max = bucket.max.max(value);
+                bucket.avg += (value - bucket.avg) / bucket.count as f64;
+            }
+            _ => self.buckets.push_back(Bucket {
+                start,
+                min: value,
+                max: value,
+                avg: value,
+                count: 1,
+            }),
+        }
+
+        let oldest = start.saturating_sub(self.retention.as_millis() as u64);
+        while self.buckets.len() > self.capacity
+            || self
+                .buckets
+                .front()
+                .is_some_and(|bucket| bucket.start < oldest)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    // the buckets that started at or after since
+    pub fn since(&self, since: u64) -> impl Iterator<Item = &Bucket> {
+        let first = self.buckets.partition_point(|bucket| bucket.start < since);
+        self.buckets.range(first..)
+    }
+
+    // (start, avg) of the buckets since `since` merged by step, a multiple of the resolution
+    pub fn averages(&self, since: u64, step: Duration) -> Vec<(u64, f64)> {
+        let step = step.as_millis() as u64;
+        let mut averages: Vec<(u64, f64, u32)> = Vec::new();
+
+        for bucket in self.since(since) {
+            let start = bucket.start - bucket.start % step;
+            match averages.last_mut() {
+                Some((last, avg, count)) if *last == start => {
+                    *count += bucket.count;
+                    *avg += (bucket.avg - *avg) * bucket.count as f64 / *count as f64;
+                }
+                _ => averages.push((start, bucket.avg, bucket.count)),
+            }
+        }
+
+        averages
+            .into_iter()
+            .map(|(start, avg, _)| (start, avg))
+            .collect()
+    }
+}
+
+// of one instance, every sample goes in every tier
+pub struct History {
+    pub tiers: Vec<Tier>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            tiers: TIERS
+                .iter()
+                .map(|(resolution, retention)| Tier {
+                    resolution: *resolution,
+                    retention: *retention,
+                    capacity: MAX_BUCKETS,
+                    buckets: collections::VecDeque::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl History {
+    pub fn new(nb_instance: usize) -> Self {
+        let mut history = Self::default();
+        history.cap(nb_instance);
+
+        history
+    }
+
+    // the more instances a counter has, the shorter the history of each, a week of minutes
+    // for up to 6 instances
+    pub fn cap(&mut self, nb_instance: usize) {
+        for tier in self.tiers.iter_mut() {
+            tier.capacity = (MAX_BUCKETS / nb_instance.max(1)).max(1);
+
+            let excess = tier.buckets.len().saturating_sub(tier.capacity);
+            tier.buckets.drain(..excess);
+        }
+    }
+
+    pub fn push(&mut self, timestamp: u64, value: f64) {
+        for tier in self.tiers.iter_mut() {
+            tier.push(timestamp, value);
+        }
+    }
+}
+
+// the finest tier showing the duration in at most count buckets, else the coarsest, and the
+// step its buckets are merged by to fit
+pub fn tier_for(duration: Duration, count: usize) -> (usize, Duration) {
+    let tier = TIERS
+        .iter()
+        .position(|(resolution, _)| *resolution * count as u32 >= duration)
+        .unwrap_or(TIERS.len() - 1);
+
+    let resolution = TIERS[tier].0;
+    let merged = duration
+        .as_millis()
+        .div_ceil(resolution.as_millis() * count as u128)
+        .max(1);
+
+    (tier, resolution * merged as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tier_for_fits_the_duration() {
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(tier_for(minute, 120), (0, Duration::from_secs(1)));
+        assert_eq!(tier_for(60 * minute, 600), (1, Duration::from_secs(10)));
+        // the coarsest tier is merged further
+        assert_eq!(
+            tier_for(Duration::from_secs(7 * 24 * HOUR), 600),
+            (2, Duration::from_secs(17 * 60))
+        );
+    }
+
+    #[test]
+    fn buckets_hold_min_max_avg() {
+        let mut history = History::default();
+        for (timestamp, value) in [(1000, 1.0), (1500, 3.0), (2000, 5.0)] {
+            history.push(timestamp, value);
+        }
+
+        let buckets = history.tiers[0]
+            .since(0)
+            .map(|bucket| (bucket.start, bucket.min, bucket.max, bucket.avg))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets, vec![(1000, 1.0, 3.0, 2.0), (2000, 5.0, 5.0, 5.0)]);
+        assert_eq!(history.tiers[1].since(0).count(), 1);
+    }
+
+    #[test]
+    fn averages_are_weighted_by_samples() {
+        let mut history = History::default();
+        for (timestamp, value) in [(0, 1.0), (500, 1.0), (1000, 4.0), (2000, 7.0)] {
+            history.push(timestamp, value);
+        }
+
+        assert_eq!(
+            history.tiers[0].averages(0, Duration::from_secs(2)),
+            vec![(0, 2.0), (2000, 7.0)]
+        );
+        assert_eq!(
+            history.tiers[0].averages(1000, Duration::from_secs(1)),
+            vec![(1000, 4.0), (2000, 7.0)]
+        );
+    }
+
+    #[test]
+    fn old_buckets_are_dropped() {
+        let mut history = History::default();
+        let hour = HOUR * 1000;
+        history.push(0, 1.0);
+        history.push(hour + 1000, 2.0);
+
+        assert_eq!(history.tiers[0].since(0).count(), 1);
+        assert_eq!(history.tiers[1].since(0).count(), 2);
+    }
+
+    #[test]
+    fn many_instances_keep_fewer_buckets() {
+        let mut history = History::new(1);
+        for second in 0..20 {
+            history.push(second * 1000, second as f64);
+        }
+        assert_eq!(history.tiers[0].since(0).count(), 20);
+
+        history.cap(MAX_BUCKETS / 10);
+        assert_eq!(history.tiers[0].since(0).next().unwrap().start, 10000);
+        assert_eq!(history.tiers[1].since(0).count(), 2);
+
+        let mut history = History::new(MAX_BUCKETS / 5);
+        for second in 0..20 {
+            history.push(second * 1000, second as f64);
+        }
+        assert_eq!(
+            history.tiers[0].averages(0, Duration::from_secs(5)),
+            vec![(15000, 17.0)]
+        );
+    }
+}
@@ -1,4 +1,15 @@
+pub mod counter;
+pub mod counter_path;
+pub mod filter;
+#[cfg(windows)]
 pub mod graphic;
+pub mod history;
+#[cfg(windows)]
 pub mod menu;
+#[cfg(windows)]
 pub mod query;
+pub mod schedule;
+pub mod source;
+pub mod unit;
+#[cfg(windows)]
 pub mod window;
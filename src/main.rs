@@ -1,5 +1,6 @@
-#![windows_subsystem = "windows"]
+#![cfg_attr(windows, windows_subsystem = "windows")]
 
+#[cfg(windows)]
 use windows_sys::{
     w,
     Win32::{
@@ -8,19 +9,23 @@ use windows_sys::{
         UI::WindowsAndMessaging::{
             AdjustWindowRect, DefWindowProcW, DispatchMessageW, GetCursorPos,
             GetMessageW, GetPropW, PostQuitMessage, RemovePropW, SendMessageW, SetPropW,
-            SetWindowPos, TranslateMessage, MINMAXINFO, MSG, SWP_NOACTIVATE, SWP_NOZORDER,
-            WM_COMMAND, WM_DESTROY, WM_DPICHANGED, WM_GETMINMAXINFO, WM_PAINT, WM_SIZE,
-            WS_OVERLAPPEDWINDOW,
+            SetWindowPos, SetWindowTextW, TranslateMessage, MINMAXINFO, MSG, SWP_NOACTIVATE,
+            SWP_NOZORDER, WM_COMMAND, WM_DESTROY, WM_DPICHANGED, WM_GETMINMAXINFO,
+            WM_INITMENUPOPUP, WM_PAINT, WM_SIZE, WS_OVERLAPPEDWINDOW,
         },
     },
 };
 
+#[cfg(windows)]
 use wgpu::SurfaceError;
 
-use std::{cmp, mem, ptr, sync::mpsc, thread, time};
+#[cfg(windows)]
+use std::{cmp, iter, mem, ptr, sync::mpsc, thread, time};
 
+#[cfg(windows)]
 use pdhv::{graphic, menu, query, window};
 
+#[cfg(windows)]
 struct App {
     menu: menu::Menu,
     graphic: graphic::Graphic,
@@ -28,8 +33,18 @@ struct App {
 
     size: (i32, i32),
     dpi: u32,
+    // as shown in the title
+    missed_ticks: u64,
 }
 
+// the viewer is a win32 window, other platforms run pdhv-agent
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("pdhv only runs on windows, use pdhv-agent to collect counters from here");
+    std::process::exit(1);
+}
+
+#[cfg(windows)]
 fn main() {
     panic!("test");
     env_logger::init();
@@ -49,6 +64,7 @@ fn main() {
 
             size: window.get_size(),
             dpi: window.get_dpi(),
+            missed_ticks: 0,
         };
 
         assert!(SetPropW(window.hwnd, w!("app"), &mut app as *mut App as _) != 0);
@@ -74,6 +90,7 @@ fn main() {
     }
 }
 
+#[cfg(windows)]
 #[allow(clippy::missing_safety_doc)]
 unsafe extern "system" fn wnd_proc(
     hwnd: HWND,
@@ -98,15 +115,85 @@ unsafe extern "system" fn wnd_proc(
                     }
                     menu::IDM_LOG_START => (*papp).query.start_logging(&mut (*papp).menu, hwnd),
                     menu::IDM_LOG_STOP => (*papp).query.stop_logging(&mut (*papp).menu),
+                    menu::IDM_REPLAY_OPEN => (*papp).query.open_replay(&mut (*papp).menu, hwnd),
+                    menu::IDM_REPLAY_PAUSE => (*papp).query.pause_replay(&mut (*papp).menu),
+                    menu::IDM_REPLAY_SPEED_1 => {
+                        (*papp).query.set_replay_speed(&mut (*papp).menu, 1.0)
+                    }
+                    menu::IDM_REPLAY_SPEED_2 => {
+                        (*papp).query.set_replay_speed(&mut (*papp).menu, 2.0)
+                    }
+                    menu::IDM_REPLAY_SPEED_10 => {
+                        (*papp).query.set_replay_speed(&mut (*papp).menu, 10.0)
+                    }
+                    menu::IDM_REPLAY_BACKWARD => (*papp).query.seek_replay(-60.0),
+                    menu::IDM_REPLAY_FORWARD => (*papp).query.seek_replay(60.0),
+                    menu::IDM_INTERVAL_100MS => (*papp)
+                        .query
+                        .set_interval(&mut (*papp).menu, time::Duration::from_millis(100)),
+                    menu::IDM_INTERVAL_500MS => (*papp)
+                        .query
+                        .set_interval(&mut (*papp).menu, time::Duration::from_millis(500)),
+                    menu::IDM_INTERVAL_1S => (*papp)
+                        .query
+                        .set_interval(&mut (*papp).menu, time::Duration::from_millis(1000)),
+                    menu::IDM_INTERVAL_5S => (*papp)
+                        .query
+                        .set_interval(&mut (*papp).menu, time::Duration::from_millis(5000)),
+                    menu::IDM_INTERVAL_15S => (*papp)
+                        .query
+                        .set_interval(&mut (*papp).menu, time::Duration::from_millis(15000)),
+                    menu::IDM_HISTORY_1MIN => (*papp)
+                        .query
+                        .set_history(&mut (*papp).menu, time::Duration::from_secs(60)),
+                    menu::IDM_HISTORY_10MIN => (*papp)
+                        .query
+                        .set_history(&mut (*papp).menu, time::Duration::from_secs(600)),
+                    menu::IDM_HISTORY_1H => (*papp)
+                        .query
+                        .set_history(&mut (*papp).menu, time::Duration::from_secs(3600)),
+                    menu::IDM_HISTORY_1D => (*papp)
+                        .query
+                        .set_history(&mut (*papp).menu, time::Duration::from_secs(24 * 3600)),
+                    menu::IDM_HISTORY_1W => (*papp)
+                        .query
+                        .set_history(&mut (*papp).menu, time::Duration::from_secs(7 * 24 * 3600)),
+                    id if menu::IDM_ADD_RANGE.contains(&id) => (*papp).query.add_offered_counter(
+                        hwnd,
+                        &mut (*papp).menu,
+                        id - menu::IDM_ADD_RANGE.start,
+                    ),
                     id if menu::IDM_REMOVE_RANGE.contains(&id) => (*papp)
                         .query
                         .remove_counter(id - menu::IDM_REMOVE_RANGE.start, &mut (*papp).menu),
+                    id if menu::IDM_COUNTER_INTERVAL_RANGE.contains(&id) => {
+                        let item = id - menu::IDM_COUNTER_INTERVAL_RANGE.start;
+                        let choice = item % menu::IDM_COUNTER_INTERVAL_CHOICES;
+                        (*papp).query.set_counter_interval(
+                            &mut (*papp).menu,
+                            item / menu::IDM_COUNTER_INTERVAL_CHOICES,
+                            query::counter_intervals().nth(choice as usize).flatten(),
+                        )
+                    }
                     _ => (),
                 }
 
                 0
             }
         },
+        WM_INITMENUPOPUP => match GetPropW(hwnd, w!("app")) {
+            0 => DefWindowProcW(hwnd, msg, wparam, lparam),
+            happ => {
+                let papp = happ as *mut App;
+
+                // the counters offered can change between two openings
+                if (*papp).menu.get_sub_menu(menu::IDM_COUNTER) == Some(wparam as isize) {
+                    (*papp).query.list_offered_counters(&mut (*papp).menu);
+                }
+
+                0
+            }
+        },
         WM_SIZE => match GetPropW(hwnd, w!("app")) {
             0 => {
                 //SendMessageW(hwnd, WM_DESTROY, 0, 0);
@@ -136,7 +223,7 @@ unsafe extern "system" fn wnd_proc(
                 let papp = happ as *mut App;
                 (*papp)
                     .graphic
-                    .update(&(*papp).query, (*papp).dpi, pt.x, pt.y);
+                    .update(&(*papp).query.snapshot(), (*papp).dpi, pt.x, pt.y);
 
                 if let Err(SurfaceError::OutOfMemory) = (*papp).graphic.render() {
                     SendMessageW(hwnd, WM_DESTROY, 0, 0);
@@ -153,7 +240,7 @@ unsafe extern "system" fn wnd_proc(
             }
             happ => {
                 let papp = happ as *mut App;
-                let nb_box = (*papp).query.counters.len();
+                let nb_box = (*papp).query.snapshot().counters.len();
                 let nb_box_x = cmp::max(
                     1,
                     cmp::min(
@@ -213,19 +300,28 @@ unsafe extern "system" fn wnd_proc(
                 0
             }
         },
-        query::WM_UPDATE_QUERY => match GetPropW(hwnd, w!("app")) {
-            0 => {
-                //SendMessageW(hwnd, WM_DESTROY, 0, 0);
-                DefWindowProcW(hwnd, msg, wparam, lparam)
-            }
-            happ => {
+        // a snapshot was published by the collector
+        query::WM_UPDATE_QUERY => {
+            let happ = GetPropW(hwnd, w!("app"));
+            if happ != 0 {
                 let papp = happ as *mut App;
-                (*papp).query.update(&mut (*papp).menu);
-                InvalidateRect(hwnd, ptr::null(), false.into());
 
-                0
+                // the deadlines the collector did not keep up with
+                let missed_ticks = (*papp).query.snapshot().missed_ticks;
+                if missed_ticks != (*papp).missed_ticks {
+                    (*papp).missed_ticks = missed_ticks;
+
+                    let title = format!("pdhv - {} missed collections", missed_ticks)
+                        .encode_utf16()
+                        .chain(iter::once(0))
+                        .collect::<Vec<_>>();
+                    SetWindowTextW(hwnd, title.as_ptr());
+                }
             }
-        },
+
+            InvalidateRect(hwnd, ptr::null(), false.into());
+            0
+        }
         _ => DefWindowProcW(hwnd, msg, wparam, lparam),
     }
 }
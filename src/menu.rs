@@ -1,9 +1,10 @@
 use windows_sys::w;
 use windows_sys::Win32::Foundation::HWND;
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    AppendMenuW, CreateMenu, DrawMenuBar, GetMenuItemCount, RemoveMenu, SetMenu, SetMenuItemInfoW,
-    MENUITEMINFOW, MFS_CHECKED, MFS_GRAYED, MFS_UNCHECKED, MF_BYCOMMAND, MF_CHECKED, MF_GRAYED,
-    MF_POPUP, MF_SEPARATOR, MF_STRING, MF_UNCHECKED, MIIM_STATE,
+    AppendMenuW, CreateMenu, DestroyMenu, DrawMenuBar, GetMenuItemCount, GetSubMenu, RemoveMenu,
+    SetMenu, SetMenuItemInfoW, MENUITEMINFOW, MFS_CHECKED, MFS_GRAYED, MFS_UNCHECKED, MF_BYCOMMAND,
+    MF_BYPOSITION, MF_CHECKED, MF_GRAYED, MF_POPUP, MF_SEPARATOR, MF_STRING, MF_UNCHECKED,
+    MIIM_STATE,
 };
 
 use std::{collections, mem, ops, ptr};
@@ -18,7 +19,78 @@ pub const IDM_LOG: isize = 6;
 pub const IDM_LOG_START: isize = 7;
 pub const IDM_LOG_STOP: isize = 8;
 
-pub const IDM_REMOVE_RANGE: ops::Range<isize> = 100..200;
+pub const IDM_REPLAY: isize = 9;
+pub const IDM_REPLAY_OPEN: isize = 10;
+pub const IDM_REPLAY_PAUSE: isize = 11;
+pub const IDM_REPLAY_SPEED_1: isize = 12;
+pub const IDM_REPLAY_SPEED_2: isize = 13;
+pub const IDM_REPLAY_SPEED_10: isize = 14;
+pub const IDM_REPLAY_BACKWARD: isize = 15;
+pub const IDM_REPLAY_FORWARD: isize = 16;
+pub const IDM_REPLAY_SEPARATOR: isize = 17;
+pub const IDM_REPLAY_SEEK_SEPARATOR: isize = 18;
+
+pub const IDM_INTERVAL: isize = 19;
+pub const IDM_INTERVAL_100MS: isize = 20;
+pub const IDM_INTERVAL_500MS: isize = 21;
+pub const IDM_INTERVAL_1S: isize = 22;
+pub const IDM_INTERVAL_5S: isize = 23;
+pub const IDM_INTERVAL_15S: isize = 24;
+
+// (id, interval in milliseconds)
+pub const IDM_INTERVALS: [(isize, u64); 5] = [
+    (IDM_INTERVAL_100MS, 100),
+    (IDM_INTERVAL_500MS, 500),
+    (IDM_INTERVAL_1S, 1000),
+    (IDM_INTERVAL_5S, 5000),
+    (IDM_INTERVAL_15S, 15000),
+];
+
+pub const IDM_HISTORY: isize = 25;
+pub const IDM_HISTORY_1MIN: isize = 26;
+pub const IDM_HISTORY_10MIN: isize = 27;
+pub const IDM_HISTORY_1H: isize = 28;
+pub const IDM_HISTORY_1D: isize = 30;
+pub const IDM_HISTORY_1W: isize = 31;
+
+// (id, history in seconds)
+pub const IDM_HISTORIES: [(isize, u64); 5] = [
+    (IDM_HISTORY_1MIN, 60),
+    (IDM_HISTORY_10MIN, 600),
+    (IDM_HISTORY_1H, 3600),
+    (IDM_HISTORY_1D, 24 * 3600),
+    (IDM_HISTORY_1W, 7 * 24 * 3600),
+];
+
+pub const IDM_COUNTER_INTERVAL: isize = 29;
+// the counters the sources offer, listed again each time the Counter menu opens
+pub const IDM_COUNTER_ADD: isize = 32;
+pub const IDM_COUNTER_ADD_NONE: isize = 33;
+
+// counter ids are below it, a removed counter frees its id, so the ranges of items per
+// counter never overlap
+pub const MAX_COUNTERS: isize = 100;
+
+pub const IDM_REMOVE_RANGE: ops::Range<isize> = 100..100 + MAX_COUNTERS;
+// a sub menu of IDM_COUNTER_INTERVAL per counter
+pub const IDM_COUNTER_INTERVAL_MENU_RANGE: ops::Range<isize> = 200..200 + MAX_COUNTERS;
+// the interval of the query, then IDM_INTERVALS
+pub const IDM_COUNTER_INTERVAL_CHOICES: isize = IDM_INTERVALS.len() as isize + 1;
+// IDM_COUNTER_INTERVAL_CHOICES items per counter, see counter_interval_id
+pub const IDM_COUNTER_INTERVAL_RANGE: ops::Range<isize> =
+    1000..1000 + MAX_COUNTERS * IDM_COUNTER_INTERVAL_CHOICES;
+// an item of IDM_COUNTER_ADD per offered counter
+pub const IDM_ADD_RANGE: ops::Range<isize> = 2000..3000;
+
+const _: () = assert!(
+    IDM_REMOVE_RANGE.end <= IDM_COUNTER_INTERVAL_MENU_RANGE.start
+        && IDM_COUNTER_INTERVAL_MENU_RANGE.end <= IDM_COUNTER_INTERVAL_RANGE.start
+        && IDM_COUNTER_INTERVAL_RANGE.end <= IDM_ADD_RANGE.start
+);
+
+pub fn counter_interval_id(counter: isize, choice: isize) -> isize {
+    IDM_COUNTER_INTERVAL_RANGE.start + counter * IDM_COUNTER_INTERVAL_CHOICES + choice
+}
 
 pub struct Menu {
     pub hmenu: isize,
@@ -38,6 +110,8 @@ impl Menu {
 
         menu.add_menu(None, IDM_COUNTER, w!("&Counter"));
         menu.add_item(Some(IDM_COUNTER), IDM_COUNTER_NEW, w!("&New"), None, false);
+        menu.add_menu(Some(IDM_COUNTER), IDM_COUNTER_ADD, w!("&Add"));
+        menu.add_item(Some(IDM_COUNTER_ADD), IDM_COUNTER_ADD_NONE, w!("None"), None, true);
         menu.add_menu(Some(IDM_COUNTER), IDM_COUNTER_REMOVE, w!("&Remove"));
         menu.add_item(
             Some(IDM_COUNTER_REMOVE),
@@ -47,11 +121,93 @@ impl Menu {
             false,
         );
         menu.add_separator(Some(IDM_COUNTER_REMOVE), IDM_COUNTER_REMOVE_SEPARATOR);
+        menu.add_menu(Some(IDM_COUNTER), IDM_COUNTER_INTERVAL, w!("&Interval"));
 
         menu.add_menu(None, IDM_LOG, w!("&Log"));
         menu.add_item(Some(IDM_LOG), IDM_LOG_START, w!("&Start"), None, false);
         menu.add_item(Some(IDM_LOG), IDM_LOG_STOP, w!("&Stop"), None, true);
 
+        menu.add_menu(None, IDM_REPLAY, w!("&Replay"));
+        menu.add_item(Some(IDM_REPLAY), IDM_REPLAY_OPEN, w!("&Open"), None, false);
+        menu.add_separator(Some(IDM_REPLAY), IDM_REPLAY_SEPARATOR);
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_PAUSE,
+            w!("&Pause"),
+            Some(false),
+            true,
+        );
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_SPEED_1,
+            w!("&1x"),
+            Some(true),
+            true,
+        );
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_SPEED_2,
+            w!("&2x"),
+            Some(false),
+            true,
+        );
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_SPEED_10,
+            w!("1&0x"),
+            Some(false),
+            true,
+        );
+        menu.add_separator(Some(IDM_REPLAY), IDM_REPLAY_SEEK_SEPARATOR);
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_BACKWARD,
+            w!("&Backward 1 min"),
+            None,
+            true,
+        );
+        menu.add_item(
+            Some(IDM_REPLAY),
+            IDM_REPLAY_FORWARD,
+            w!("&Forward 1 min"),
+            None,
+            true,
+        );
+
+        menu.add_menu(None, IDM_INTERVAL, w!("&Interval"));
+        for (id, name) in [
+            (IDM_INTERVAL_100MS, w!("100 &ms")),
+            (IDM_INTERVAL_500MS, w!("&500 ms")),
+            (IDM_INTERVAL_1S, w!("&1 s")),
+            (IDM_INTERVAL_5S, w!("&5 s")),
+            (IDM_INTERVAL_15S, w!("1&5 s")),
+        ] {
+            menu.add_item(
+                Some(IDM_INTERVAL),
+                id,
+                name,
+                Some(id == IDM_INTERVAL_1S),
+                false,
+            );
+        }
+
+        menu.add_menu(None, IDM_HISTORY, w!("&History"));
+        for (id, name) in [
+            (IDM_HISTORY_1MIN, w!("&1 min")),
+            (IDM_HISTORY_10MIN, w!("1&0 min")),
+            (IDM_HISTORY_1H, w!("1 &h")),
+            (IDM_HISTORY_1D, w!("1 &day")),
+            (IDM_HISTORY_1W, w!("1 &week")),
+        ] {
+            menu.add_item(
+                Some(IDM_HISTORY),
+                id,
+                name,
+                Some(id == IDM_HISTORY_1MIN),
+                false,
+            );
+        }
+
         menu
     }
 
@@ -136,6 +292,26 @@ impl Menu {
         DrawMenuBar(self.hwnd);
     }
 
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn remove_menu(&mut self, parent_id: Option<isize>, menu_id: isize) {
+        let parent = match parent_id {
+            Some(parent_id) => self.sub_menus.get(&parent_id).unwrap().unwrap(),
+            None => self.hmenu,
+        };
+
+        // a sub menu has no id of its own in its parent
+        if let Some(Some(hmenu)) = self.sub_menus.remove(&menu_id) {
+            if let Some(pos) =
+                (0..GetMenuItemCount(parent)).find(|pos| GetSubMenu(parent, *pos) == hmenu)
+            {
+                RemoveMenu(parent, pos as u32, MF_BYPOSITION);
+            }
+            DestroyMenu(hmenu);
+        }
+
+        DrawMenuBar(self.hwnd);
+    }
+
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn add_separator(&mut self, id: Option<isize>, separator_id: isize) {
         match id {
@@ -238,6 +414,11 @@ impl Menu {
         DrawMenuBar(self.hwnd);
     }
 
+    // the handle of a sub menu, as given by WM_INITMENUPOPUP
+    pub fn get_sub_menu(&self, menu_id: isize) -> Option<isize> {
+        self.sub_menus.get(&menu_id).copied().flatten()
+    }
+
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn get_item_count(&self, menu_id: Option<isize>) -> i32 {
         match menu_id {
@@ -1,316 +1,527 @@
 use windows_sys::{
     Win32::{
-        Foundation::{ERROR_SUCCESS, HWND, SYSTEMTIME},
+        Foundation::{FILETIME, HWND, SYSTEMTIME},
         System::{
-            Performance::{
-                PdhAddCounterW, PdhBrowseCountersW, PdhCloseQuery, PdhCollectQueryData,
-                PdhGetFormattedCounterArrayW, PdhOpenQueryW, PDH_BROWSE_DLG_CONFIG_W,
-                PDH_FMT_COUNTERVALUE_ITEM_W, PDH_FMT_DOUBLE, PDH_MAX_COUNTER_PATH, PDH_MORE_DATA,
-            },
             SystemInformation::GetLocalTime,
+            Time::{FileTimeToSystemTime, SystemTimeToTzSpecificLocalTime},
         },
         UI::{
-            WindowsAndMessaging::{SendMessageW, WM_USER},
-            Controls::Dialogs::{OPENFILENAMEW, GetSaveFileNameW},
+            WindowsAndMessaging::{PostMessageW, WM_COMMAND, WM_USER},
+            Controls::Dialogs::{OPENFILENAMEW, GetOpenFileNameW, GetSaveFileNameW},
         },
     },
     w,
 };
 
-use random_color::{Luminosity, RandomColor};
+use serde::{Deserialize, Serialize};
 
 use std::{
-    cmp, collections, fs,
-    io::Write,
+    collections, fs,
+    io::{self, Write},
     iter, mem, ptr,
-    sync::mpsc::{self, TryRecvError},
-    thread,
+    sync::{Arc, Mutex},
     time::{self, Duration},
     env,
     path,
 };
 
-use super::menu;
+use super::{
+    counter::{self, CounterV2},
+    counter_path::CounterPath,
+    filter::InstanceFilter,
+    menu,
+    schedule::{self, Scheduler},
+    source,
+    unit::{Metadata, Unit},
+};
 
 pub const WM_UPDATE_QUERY: u32 = WM_USER + 1;
-pub const SAMPLE_COUNT: usize = 20;
-
-pub struct CounterV2 {
-    pub path: Vec<u16>,
-    hcounter: isize,
-    data: collections::VecDeque<f64>,
-
-    pub interpolated_curves: Vec<InterpolatedCurve>,
+pub const DEFAULT_HISTORY: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize)]
+struct SavedCounter {
+    path: CounterPath,
+    #[serde(default, skip_serializing_if = "InstanceFilter::is_empty")]
+    filter: InstanceFilter,
+    // guessed from the path when missing
+    #[serde(default)]
+    metadata: Option<Metadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interval_ms: Option<u64>,
+}
 
-    nb_instance: usize,
-    instance_names: Vec<String>,
+// older saves only hold the paths
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    Counter(SavedCounter),
+    Path(CounterPath),
+}
 
+// what the chart of a counter shows, as of its last collection
+#[derive(Clone)]
+pub struct CounterSnapshot {
+    pub label: String,
+    pub unit: Unit,
+    // between two points, and the deadline the last sample came at
+    pub step: Duration,
+    pub updated: Option<u64>,
+    pub last_values: Vec<(String, f64)>,
+
+    pub interpolated_curves: Arc<Vec<counter::InterpolatedCurve>>,
     pub instance_colors: Vec<[u8; 4]>,
 
     pub max: [f64; 2],
     pub avg: [f64; 2],
 }
 
-impl CounterV2 {
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn new(hwnd: HWND, hquery: isize, path: Option<Vec<u16>>) -> Option<Self> {
-        let path = path.unwrap_or_else(|| {
-            let mut path_buffer =
-                Vec::from_iter(iter::repeat(0_u16).take(PDH_MAX_COUNTER_PATH as usize));
-
-            let mut bw_config: PDH_BROWSE_DLG_CONFIG_W = mem::zeroed();
-            bw_config._bitfield = 0b0000_0000_0000_0000_0000_0001_0001_0111;
-            bw_config.hWndOwner = hwnd;
-            bw_config.szReturnPathBuffer = path_buffer.as_mut_ptr();
-            bw_config.cchReturnPathLength = PDH_MAX_COUNTER_PATH;
-            bw_config.CallBackStatus = ERROR_SUCCESS as _;
-            bw_config.szDialogBoxCaption = &mut 0;
-
-            if PdhBrowseCountersW(&bw_config) != ERROR_SUCCESS || path_buffer[0] == 0 {
-                return Vec::new();
-            };
+impl CounterSnapshot {
+    pub fn elapsed(&self) -> Duration {
+        self.updated.map_or(Duration::ZERO, |updated| {
+            Duration::from_millis(schedule::now_millis().saturating_sub(updated))
+        })
+    }
+}
 
-            path_buffer.retain(|&c| c != 0);
-            path_buffer.push(0);
+// published by the collector after every collection, the counters are in the order they
+// were added
+#[derive(Default)]
+pub struct Snapshot {
+    pub counters: Vec<CounterSnapshot>,
+    // charted by every counter, up to its last sample
+    pub history: Duration,
+    // deadlines of a group that went by without a collection, the collector was busy
+    pub missed_ticks: u64,
+}
 
-            path_buffer
-        });
+// collects on the scheduler thread, the window only locks it to change the counters
+struct Collector {
+    hwnd: HWND,
+    sources: Vec<Box<dyn source::CounterSource>>,
+    hfile: Option<fs::File>,
+    is_logging: bool,
+    counters: collections::HashMap<usize, CounterV2>,
+    // the ids of the counters in the order they were added
+    order: Vec<usize>,
+    // by source, when it was last collected
+    collected_at: collections::HashMap<usize, time::Instant>,
+    interval: Duration,
+    history: Duration,
+    groups: schedule::Groups,
+    missed_ticks: u64,
+}
 
-        let mut hcounter = 0;
-        if PdhAddCounterW(hquery, path.as_ptr(), 0, &mut hcounter) != ERROR_SUCCESS {
-            return None;
+impl Collector {
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn insert_counter(&mut self, menu: &mut menu::Menu, counter_v2: CounterV2) {
+        // the lowest free id, its menu items are free too
+        let id = match (0..menu::MAX_COUNTERS as usize).find(|id| !self.counters.contains_key(id)) {
+            Some(id) => id,
+            None => {
+                eprintln!(
+                    "Unable to add {}, already {} counters",
+                    counter_v2.path,
+                    menu::MAX_COUNTERS
+                );
+                self.sources[counter_v2.source].remove_counter(counter_v2.hcounter);
+                return;
+            }
         };
 
-        Some(Self {
-            path,
-            hcounter,
-            data: collections::VecDeque::new(),
-
-            nb_instance: 0,
-            instance_names: Vec::new(),
+        let name = counter_v2.path.to_wide();
+        menu.add_item(
+            Some(menu::IDM_COUNTER_REMOVE),
+            id as isize + menu::IDM_REMOVE_RANGE.start,
+            name.as_ptr(),
+            None,
+            false,
+        );
 
-            interpolated_curves: Vec::new(),
+        // the interval of the query, or one of its own
+        let menu_id = id as isize + menu::IDM_COUNTER_INTERVAL_MENU_RANGE.start;
+        menu.add_menu(Some(menu::IDM_COUNTER_INTERVAL), menu_id, name.as_ptr());
+        for (choice, interval) in counter_intervals().enumerate() {
+            let label = match interval {
+                None => "&Query".to_string(),
+                Some(interval) if interval.subsec_millis() != 0 => {
+                    format!("{} ms", interval.as_millis())
+                }
+                Some(interval) => format!("{} s", interval.as_secs()),
+            };
 
-            instance_colors: Vec::new(),
+            menu.add_item(
+                Some(menu_id),
+                menu::counter_interval_id(id as isize, choice as isize),
+                label
+                    .encode_utf16()
+                    .chain(iter::once(0))
+                    .collect::<Vec<_>>()
+                    .as_ptr(),
+                Some(interval == counter_v2.interval),
+                false,
+            );
+        }
 
-            max: [0.0, 0.0],
-            avg: [0.0, 0.0],
-        })
+        self.counters.insert(id, counter_v2);
+        self.order.push(id);
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn update(
-        &mut self,
-    ) -> (
-        &Vec<u16>,
-        Option<impl iter::Iterator<Item = (&f64, &String)>>,
-    ) {
-        let mut buffer_size = 0;
-        let mut item_count = 0;
-
-        if PdhGetFormattedCounterArrayW(
-            self.hcounter,
-            PDH_FMT_DOUBLE,
-            &mut buffer_size,
-            &mut item_count,
-            ptr::null_mut(),
-        ) != PDH_MORE_DATA
-        {
-            return (&self.path, None);
-        };
+    // the sources after it move down
+    fn remove_source(&mut self, source: usize) {
+        self.sources.remove(source).close();
 
-        let mut item_buffer = Vec::from_iter(
-            iter::repeat(mem::zeroed::<PDH_FMT_COUNTERVALUE_ITEM_W>())
-                .take(buffer_size as usize / mem::size_of::<PDH_FMT_COUNTERVALUE_ITEM_W>() + 1),
-        );
+        for counter in self.counters.values_mut() {
+            if counter.source > source {
+                counter.source -= 1;
+            }
+        }
+        self.collected_at = self
+            .collected_at
+            .drain()
+            .filter(|(index, _)| *index != source)
+            .map(|(index, at)| (if index > source { index - 1 } else { index }, at))
+            .collect();
+    }
 
-        if PdhGetFormattedCounterArrayW(
-            self.hcounter,
-            PDH_FMT_DOUBLE,
-            &mut buffer_size,
-            &mut item_count,
-            item_buffer.as_mut_ptr(),
-        ) != ERROR_SUCCESS
-        {
-            return (&self.path, None);
-        };
+    fn interval_of(&self, counter: &CounterV2) -> Duration {
+        counter.interval.unwrap_or(self.interval)
+    }
 
-        // we now have for sure a sample
+    fn intervals(&self) -> collections::BTreeSet<Duration> {
+        self.counters
+            .values()
+            .map(|counter| self.interval_of(counter))
+            .collect()
+    }
 
-        if self.nb_instance != item_count as usize {
-            self.nb_instance = item_count as usize;
+    fn tick(&self) -> Duration {
+        schedule::tick(self.intervals().into_iter(), self.interval)
+    }
 
-            self.data =
-                collections::VecDeque::from_iter(iter::repeat(0.0).take(self.nb_instance * 20));
-            self.interpolated_curves = Vec::new();
+    // true when a counter was due
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn update(&mut self, deadline: u64) -> bool {
+        let intervals = self.intervals();
+        let due = self.groups.due(&intervals, deadline);
+        if due.is_empty() {
+            return false;
+        }
 
-            self.instance_colors = (0..self.nb_instance)
-                .map(|_| {
-                    let tmp = RandomColor::new()
-                        .luminosity(Luminosity::Light)
-                        .to_rgb_array();
+        let missed = schedule::missed(&due, deadline);
+        self.missed_ticks += missed.len() as u64;
 
-                    [tmp[0], tmp[1], tmp[2], 255]
-                })
-                .collect();
+        let interval = self.interval;
+        let is_due = |counter: &CounterV2| {
+            let counter_interval = counter.interval.unwrap_or(interval);
+            due.iter()
+                .any(|(due_interval, _)| *due_interval == counter_interval)
+        };
 
-            self.instance_names = item_buffer
-                .iter()
-                .take(self.nb_instance)
-                .map(|item| {
-                    let mut curr = item.szName;
-                    String::from_utf16(
-                        iter::repeat_with(|| {
-                            let tmp = *curr;
-                            curr = curr.add(1);
-                            tmp
-                        })
-                        .take_while(|tmp| tmp != &0)
-                        .collect::<Vec<u16>>()
-                        .as_slice(),
-                    )
-                    .unwrap()
-                })
-                .collect();
+        // the derived source, if any, is first and reads the others
+        let mut due_sources = self
+            .counters
+            .values()
+            .filter(|counter| is_due(counter))
+            .map(|counter| counter.source)
+            .collect::<collections::BTreeSet<_>>();
+        if due_sources.contains(&0) {
+            due_sources.extend(self.sources[0].reads().into_iter().map(|source| source + 1));
         }
 
-        self.data.extend(
-            item_buffer
-                .iter()
-                .take(self.nb_instance)
-                .map(|item| item.FmtValue.Anonymous.doubleValue),
-        );
-        drop(self.data.drain(
-            0..cmp::max(
-                0,
-                self.data.len() as isize - (self.nb_instance * SAMPLE_COUNT) as isize,
-            ) as usize,
-        ));
-
-        self.interpolated_curves = (0..self.nb_instance)
-            .map(|of| {
-                InterpolatedCurve::new(
-                    self.data
-                        .iter()
-                        .skip(of)
-                        .step_by(self.nb_instance)
-                        .map(|val| *val as f32),
-                )
+        // only those sources are collected, over the time since their own last collection
+        let now = time::Instant::now();
+        let collected = self
+            .sources
+            .iter_mut()
+            .enumerate()
+            .map(|(source, counter_source)| {
+                due_sources.contains(&source) && {
+                    let elapsed = self
+                        .collected_at
+                        .insert(source, now)
+                        .map_or(Duration::ZERO, |last| now - last);
+                    counter_source.collect(elapsed)
+                }
             })
             .collect::<Vec<_>>();
 
-        let (mut max, mut tmp_max, mut avg) = (f64::MIN, f64::MIN, 0.0);
-        for of in (0..self.data.len()).step_by(self.nb_instance) {
-            for tmp_value in self.data.range(of..(of + self.nb_instance)) {
-                if *tmp_value > tmp_max {
-                    tmp_max = *tmp_value;
-                }
+        if let Some((first, others)) = self.sources.split_first_mut() {
+            if collected[0] {
+                first.derive(others, &collected[1..]);
+            }
+        }
+
+        if collected.iter().any(|&is_collected| is_collected) {
+            let (sources, history) = (&self.sources, self.history);
+
+            // the missed deadlines are logged without data, in order
+            let mut missed_lines = Vec::new();
+            if self.is_logging {
+                missed_lines = missed
+                    .iter()
+                    .map(|(missed, missed_interval)| {
+                        let line = self
+                            .counters
+                            .values()
+                            .filter(|counter| {
+                                counter.interval.unwrap_or(interval) == *missed_interval
+                            })
+                            .map(|counter| counter.path.to_string() + " ; (no data) ; ")
+                            .collect::<String>();
+
+                        (*missed, line)
+                    })
+                    .collect::<Vec<_>>();
             }
 
-            if tmp_max > max {
-                max = tmp_max;
+            // only the due counters are updated and logged
+            let datas = self
+                .counters
+                .values_mut()
+                .filter(|counter| is_due(counter))
+                .map(|counter| {
+                    let counter_interval = counter.interval.unwrap_or(interval);
+                    counter.update(
+                        collected[counter.source].then_some(sources[counter.source].as_ref()),
+                        deadline,
+                        counter_interval,
+                        history,
+                    )
+                });
+
+            if self.is_logging {
+                let tmp = datas
+                    .map(|(counter_path, unit, instance)| {
+                        counter_path.to_string()
+                            + &(if let Some(instance_data) = instance {
+                                instance_data
+                                    .map(|(val, name)| {
+                                        // the formatted value follows the raw one, for reading
+                                        let val = match unit {
+                                            Unit::None => val.to_string(),
+                                            unit => format!("{} = {}", val, unit.format(*val)),
+                                        };
+
+                                        " ; (".to_string() + name + ", " + &val + ")"
+                                    })
+                                    .collect::<String>()
+                                    + " ; "
+                            } else {
+                                " ; (no data) ; ".to_string()
+                            })
+                    })
+                    .collect::<String>();
+
+                let hfile = self.hfile.as_ref().unwrap();
+                if missed_lines
+                    .iter()
+                    .map(|(missed, line)| (*missed, line))
+                    .chain(iter::once((deadline, &tmp)))
+                    .try_for_each(|(timestamp, line)| write_log_line(hfile, timestamp, line))
+                    .is_err()
+                {
+                    // the menu belongs to the window thread
+                    self.is_logging = false;
+                    PostMessageW(self.hwnd, WM_COMMAND, menu::IDM_LOG_STOP as usize, 0);
+                }
+            } else {
+                datas.for_each(drop);
             }
+        }
 
-            avg += tmp_max / (self.data.len() / self.nb_instance) as f64;
-            tmp_max = f64::MIN;
-        }
-        self.max[0] = self.max[1];
-        self.max[1] = max;
-        self.avg[0] = self.avg[1];
-        self.avg[1] = avg;
-
-        (
-            &self.path,
-            Some(
-                self.data
-                    .range((self.data.len() - self.nb_instance)..self.data.len())
-                    .zip(self.instance_names.iter()),
-            ),
-        )
+        true
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub fn get_data_by_instance(
-        &self,
-    ) -> Option<impl iter::Iterator<Item = impl iter::Iterator<Item = &f64>>> {
-        if !self.data.is_empty() {
-            Some(
-                (0..self.nb_instance)
-                    .map(|offset| self.data.iter().skip(offset).step_by(self.nb_instance)),
-            )
-        } else {
-            None
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            counters: self
+                .order
+                .iter()
+                .map(|id| {
+                    let counter = &self.counters[id];
+
+                    CounterSnapshot {
+                        label: counter.label(),
+                        unit: counter.metadata.unit,
+                        step: counter.step,
+                        updated: counter.updated,
+                        last_values: counter
+                            .last_values()
+                            .map(|(name, value)| (name.clone(), value))
+                            .collect(),
+
+                        interpolated_curves: counter.interpolated_curves.clone(),
+                        instance_colors: counter.instance_colors.clone(),
+
+                        max: counter.max,
+                        avg: counter.avg,
+                    }
+                })
+                .collect(),
+            history: self.history,
+            missed_ticks: self.missed_ticks,
         }
     }
 }
 
 pub struct QueryV2 {
-    hquery: isize,
-    _tx: mpsc::Sender<()>,
+    collector: Arc<Mutex<Collector>>,
+    snapshot: Arc<Mutex<Arc<Snapshot>>>,
+    scheduler: Scheduler,
+    replays: Vec<(usize, Arc<Mutex<source::replay::Playback>>)>,
     save_path: path::PathBuf,
-    hfile: Option<fs::File>,
-    is_logging: bool,
-    pub counters: collections::HashMap<usize, CounterV2>,
-    last_id: usize,
-    pub last_update: time::Instant,
+    // as listed in the Counter > Add menu
+    offered: Vec<CounterPath>,
 }
 
 impl QueryV2 {
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn new(hwnd: HWND, menu: &mut menu::Menu) -> Self {
-        let mut hquery = 0;
-        assert!(PdhOpenQueryW(ptr::null(), 0, &mut hquery) == ERROR_SUCCESS);
-
-        let (_tx, rx) = mpsc::channel::<()>();
-        thread::spawn(move || loop {
-            if let Err(TryRecvError::Disconnected) = rx.try_recv() {
-                break;
-            }
-
-            SendMessageW(hwnd, WM_UPDATE_QUERY, 0, 0);
-            thread::sleep(Duration::from_millis(990));
-        });
-
         println!("{}", env::var("APP_DATA").expect("No APP_DATA directory"));
 
-        let mut query_v2 = Self {
-            hquery,
-            _tx,
-            save_path: env::current_dir().unwrap().join("save.json"),
+        let save_path = env::current_dir().unwrap().join("save.json");
+        let mut collector = Collector {
+            hwnd,
+            sources: source::default_sources(),
             hfile: None,
             is_logging: false,
             counters: collections::HashMap::new(),
-            last_id: 0,
-            last_update: time::Instant::now(),
+            order: Vec::new(),
+            collected_at: collections::HashMap::new(),
+            interval: counter::DEFAULT_INTERVAL,
+            history: DEFAULT_HISTORY,
+            groups: schedule::Groups::default(),
+            missed_ticks: 0,
         };
 
-        let saved_paths: Vec<Vec<u16>> = fs::read_to_string(&query_v2.save_path)
+        // agents to chart, as a comma separated list of address[:port]
+        let mut announced_paths = Vec::new();
+        for address in env::var("PDHV_AGENTS").unwrap_or_default().split(',') {
+            if let Some(agent) = source::agent::Agent::connect(address.trim()) {
+                announced_paths.extend(agent.paths());
+                // no counter yet, so the agents can go first and claim their paths
+                collector.sources.insert(0, Box::new(agent));
+            }
+        }
+
+        // counters computed from the others, derived once every other source collected
+        if let Some(definitions) = env::current_dir()
+            .ok()
+            .and_then(|dir| source::load_definitions(&dir.join("derived.json")))
+        {
+            let derived = source::derived::Derived::new(definitions, &mut collector.sources);
+            collector.sources.insert(0, Box::new(derived));
+        }
+
+        let saved_counters: Vec<serde_json::Value> = fs::read_to_string(&save_path)
             .ok()
             .and_then(|string| serde_json::from_str(&string).ok())
             .unwrap_or_default();
 
-        for path in saved_paths {
-            query_v2.add_counter(hwnd, menu, Some(path));
+        // one invalid path or pattern only drops that counter
+        for saved in saved_counters {
+            let (path, filter, metadata, interval_ms) = match serde_json::from_value(saved) {
+                Ok(Saved::Counter(counter)) => (
+                    counter.path,
+                    counter.filter,
+                    counter.metadata,
+                    counter.interval_ms,
+                ),
+                Ok(Saved::Path(path)) => (path, InstanceFilter::default(), None, None),
+                Err(err) => {
+                    eprintln!("Unable to load saved counter err({})", err);
+                    continue;
+                }
+            };
+
+            if let Some(mut counter_v2) = CounterV2::new(&mut collector.sources, path) {
+                counter_v2.filter = filter;
+                counter_v2.interval = interval_ms.map(Duration::from_millis);
+                if let Some(metadata) = metadata {
+                    counter_v2.metadata = metadata;
+                }
+                collector.insert_counter(menu, counter_v2);
+            }
+        }
+
+        for path in announced_paths {
+            if !collector.counters.values().any(|counter| counter.path == path) {
+                if let Some(counter_v2) = CounterV2::new(&mut collector.sources, path) {
+                    collector.insert_counter(menu, counter_v2);
+                }
+            }
+        }
+
+        // the first collection, on the last deadline that went by
+        let tick = collector.tick();
+        let now = schedule::now_millis();
+        collector.update(now - now % tick.as_millis() as u64);
+
+        let snapshot = Arc::new(Mutex::new(Arc::new(collector.snapshot())));
+        let collector = Arc::new(Mutex::new(collector));
+
+        let (thread_collector, thread_snapshot) = (collector.clone(), snapshot.clone());
+        let scheduler = Scheduler::new(tick, move |deadline| {
+            let mut collector = thread_collector.lock().unwrap();
+
+            if collector.update(deadline) {
+                *thread_snapshot.lock().unwrap() = Arc::new(collector.snapshot());
+                PostMessageW(hwnd, WM_UPDATE_QUERY, 0, 0);
+            }
+
+            collector.tick()
+        });
+
+        Self {
+            collector,
+            snapshot,
+            scheduler,
+            replays: Vec::new(),
+            save_path,
+            offered: Vec::new(),
         }
+    }
+
+    // never waits on a collection
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.lock().unwrap().clone()
+    }
 
-        query_v2.update(menu);
-        query_v2
+    // the counters changed outside of a collection
+    fn publish(&self, collector: &Collector) {
+        *self.snapshot.lock().unwrap() = Arc::new(collector.snapshot());
+        self.scheduler.set_tick(collector.tick());
     }
 
     #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn close(&self) {
-        assert!(PdhCloseQuery(self.hquery) == ERROR_SUCCESS);
+    pub unsafe fn close(&mut self) {
+        let mut collector = self.collector.lock().unwrap();
+
+        for counter_source in collector.sources.iter_mut() {
+            counter_source.close();
+        }
 
         if let Ok(data) = serde_json::to_string(
-            &self
+            &collector
                 .counters
                 .values()
-                .map(|counter| &counter.path)
+                .filter(|counter| !self.replays.iter().any(|(source, _)| *source == counter.source))
+                .map(|counter| SavedCounter {
+                    path: counter.path.clone(),
+                    filter: counter.filter.clone(),
+                    metadata: Some(counter.metadata.clone()),
+                    interval_ms: counter.interval.map(|interval| interval.as_millis() as u64),
+                })
                 .collect::<Vec<_>>(),
         ) {
             fs::write(&self.save_path, data)
                 .unwrap_or_else(|err| eprintln!("Unable to save counters path err({})", err));
         };
+
+        // nothing left to collect until the scheduler stops
+        collector.counters.clear();
+        collector.order.clear();
+        collector.sources.clear();
     }
 
     #[allow(clippy::missing_safety_doc)]
@@ -318,88 +529,159 @@ impl QueryV2 {
         &mut self,
         hwnd: HWND,
         menu: &mut menu::Menu,
-        path: Option<Vec<u16>>,
+        path: Option<CounterPath>,
     ) {
-        if let Some(counter_v2) = CounterV2::new(hwnd, self.hquery, path) {
+        // the dialog is modal, the collector keeps going meanwhile
+        let path = match path {
+            Some(path) => path,
+            None => match source::pdh::browse_counters(hwnd) {
+                Some(path) => path,
+                None => return,
+            },
+        };
+
+        let mut collector = self.collector.lock().unwrap();
+        if let Some(counter_v2) = CounterV2::new(&mut collector.sources, path) {
+            collector.insert_counter(menu, counter_v2);
+            self.publish(&collector);
+        };
+    }
+
+    // the counters the sources offer that are not charted yet
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn list_offered_counters(&mut self, menu: &mut menu::Menu) {
+        for index in 0..self.offered.len() as isize {
+            menu.remove_item(Some(menu::IDM_COUNTER_ADD), menu::IDM_ADD_RANGE.start + index);
+        }
+        menu.remove_item(Some(menu::IDM_COUNTER_ADD), menu::IDM_COUNTER_ADD_NONE);
+
+        let collector = self.collector.lock().unwrap();
+        self.offered = collector
+            .sources
+            .iter()
+            .flat_map(|counter_source| counter_source.offers())
+            .filter(|path| !collector.counters.values().any(|counter| counter.path == *path))
+            .take(menu::IDM_ADD_RANGE.len())
+            .collect();
+
+        if self.offered.is_empty() {
+            menu.add_item(
+                Some(menu::IDM_COUNTER_ADD),
+                menu::IDM_COUNTER_ADD_NONE,
+                w!("None"),
+                None,
+                true,
+            );
+        }
+        for (index, path) in self.offered.iter().enumerate() {
             menu.add_item(
-                Some(menu::IDM_COUNTER_REMOVE),
-                self.last_id as isize + 1 + menu::IDM_REMOVE_RANGE.start,
-                counter_v2.path.as_ptr(),
+                Some(menu::IDM_COUNTER_ADD),
+                menu::IDM_ADD_RANGE.start + index as isize,
+                path.to_wide().as_ptr(),
                 None,
                 false,
             );
+        }
+    }
 
-            self.counters.insert(self.last_id + 1, counter_v2);
-            self.last_id += 1;
-        };
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn add_offered_counter(&mut self, hwnd: HWND, menu: &mut menu::Menu, index: isize) {
+        if let Some(path) = self.offered.get(index as usize).cloned() {
+            self.add_counter(hwnd, menu, Some(path));
+        }
     }
 
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn remove_counter(&mut self, id: isize, menu: &mut menu::Menu) {
-        menu.remove_item(
-            Some(menu::IDM_COUNTER_REMOVE),
-            id + menu::IDM_REMOVE_RANGE.start,
-        );
-
-        self.counters.remove(&(id as usize)).unwrap();
+        remove_counter_menus(menu, id);
+
+        let mut collector = self.collector.lock().unwrap();
+        let counter_v2 = collector.counters.remove(&(id as usize)).unwrap();
+        collector.order.retain(|added| *added != id as usize);
+        collector.sources[counter_v2.source].remove_counter(counter_v2.hcounter);
+        drop_unused_replays(&mut self.replays, menu, &mut collector);
+        self.publish(&collector);
     }
 
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn remove_all_counter(&mut self, menu: &mut menu::Menu) {
-        for (id, _) in self.counters.drain() {
-            menu.remove_item(
-                Some(menu::IDM_COUNTER_REMOVE),
-                id as isize + menu::IDM_REMOVE_RANGE.start,
+        let mut collector = self.collector.lock().unwrap();
+        let collector = &mut *collector;
+
+        collector.order.clear();
+        for (id, counter_v2) in collector.counters.drain() {
+            remove_counter_menus(menu, id as isize);
+            collector.sources[counter_v2.source].remove_counter(counter_v2.hcounter);
+        }
+        drop_unused_replays(&mut self.replays, menu, collector);
+        self.publish(collector);
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn set_interval(&mut self, menu: &mut menu::Menu, interval: Duration) {
+        let mut collector = self.collector.lock().unwrap();
+        collector.interval = interval;
+        self.publish(&collector);
+
+        for (id, item_interval) in menu::IDM_INTERVALS {
+            menu.set_item_state_by_id(
+                Some(menu::IDM_INTERVAL),
+                id,
+                Some(Duration::from_millis(item_interval) == interval),
+                false,
             );
         }
     }
 
+    // None follows the interval of the query
     #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn update(&mut self, menu: &mut menu::Menu) {
-        if PdhCollectQueryData(self.hquery) == ERROR_SUCCESS {
-            let datas = self.counters.values_mut().map(|counter| counter.update());
+    pub unsafe fn set_counter_interval(
+        &mut self,
+        menu: &mut menu::Menu,
+        id: isize,
+        interval: Option<Duration>,
+    ) {
+        let mut collector = self.collector.lock().unwrap();
+        let collector = &mut *collector;
+        let counter = match collector.counters.get_mut(&(id as usize)) {
+            Some(counter) => counter,
+            None => return,
+        };
 
-            if self.is_logging {
-                let mut sys_t: SYSTEMTIME = mem::zeroed();
-                GetLocalTime(&mut sys_t);
+        counter.interval = interval;
+        counter.chart(interval.unwrap_or(collector.interval), collector.history);
+        self.publish(collector);
 
-                let tmp = datas
-                    .map(|(counter_path, instance)| {
-                        String::from_utf16(counter_path.as_slice()).unwrap()
-                            + &(if let Some(instance_data) = instance {
-                                instance_data
-                                    .map(|(val, name)| {
-                                        " ; (".to_string() + name + ", " + &val.to_string() + ")"
-                                    })
-                                    .collect::<String>()
-                                    + " ; "
-                            } else {
-                                " ; (no data) ; ".to_string()
-                            })
-                    })
-                    .collect::<String>();
+        for (choice, item_interval) in counter_intervals().enumerate() {
+            menu.set_item_state_by_id(
+                Some(id + menu::IDM_COUNTER_INTERVAL_MENU_RANGE.start),
+                menu::counter_interval_id(id, choice as isize),
+                Some(item_interval == interval),
+                false,
+            );
+        }
+    }
 
-                if writeln!(
-                    self.hfile.as_ref().unwrap(),
-                    "D{}-{}-{} T{}:{}:{}.{} ; {}",
-                    sys_t.wYear,
-                    sys_t.wMonth,
-                    sys_t.wDay,
-                    sys_t.wHour,
-                    sys_t.wMinute,
-                    sys_t.wSecond,
-                    sys_t.wMilliseconds,
-                    tmp
-                )
-                .is_err()
-                {
-                    self.stop_logging(menu);
-                }
-            } else {
-                datas.for_each(drop);
-            }
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn set_history(&mut self, menu: &mut menu::Menu, history: Duration) {
+        let mut collector = self.collector.lock().unwrap();
+        let collector = &mut *collector;
+        collector.history = history;
+
+        // charted again from what they already hold
+        for counter in collector.counters.values_mut() {
+            let interval = counter.interval.unwrap_or(collector.interval);
+            counter.chart(interval, history);
+        }
+        self.publish(collector);
 
-            self.last_update = time::Instant::now();
+        for (id, item_history) in menu::IDM_HISTORIES {
+            menu.set_item_state_by_id(
+                Some(menu::IDM_HISTORY),
+                id,
+                Some(Duration::from_secs(item_history) == history),
+                false,
+            );
         }
     }
 
@@ -427,20 +709,22 @@ impl QueryV2 {
 
         let file_name_string = String::from_utf16(file_name.as_slice()).unwrap();
 
+        let mut collector = self.collector.lock().unwrap();
+
         if let Ok(hfile) = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .open(file_name_string.trim_matches(char::from(0)))
         {
-            self.hfile = Some(hfile);
+            collector.hfile = Some(hfile);
         }
 
-        if self
+        if collector
             .hfile
             .as_ref()
             .unwrap()
             .set_len(0)
-            .and(writeln!(self.hfile.as_ref().unwrap(), "copyright pdhv.fr",))
+            .and(writeln!(collector.hfile.as_ref().unwrap(), "copyright pdhv.fr",))
             .is_err()
         {
             return;
@@ -450,7 +734,7 @@ impl QueryV2 {
         menu.set_item_state_by_id(Some(menu::IDM_LOG), menu::IDM_LOG_START, None, true);
         menu.set_item_state_by_id(Some(menu::IDM_LOG), menu::IDM_LOG_STOP, None, false);
 
-        self.is_logging = true;
+        collector.is_logging = true;
     }
 
     #[allow(clippy::missing_safety_doc)]
@@ -459,56 +743,202 @@ impl QueryV2 {
         menu.set_item_state_by_id(Some(menu::IDM_LOG), menu::IDM_LOG_START, None, false);
         menu.set_item_state_by_id(Some(menu::IDM_LOG), menu::IDM_LOG_STOP, None, true);
 
-        self.is_logging = false;
+        self.collector.lock().unwrap().is_logging = false;
     }
-}
 
-pub struct InterpolatedCurve {
-    pub n: usize,
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open_replay(&mut self, menu: &mut menu::Menu, hwnd: HWND) {
+        let mut file_name = vec![0_u16; 256];
 
-    a: Vec<f32>,
-    b: Vec<f32>,
-    c: Vec<f32>,
-    d: Vec<f32>,
-}
+        let mut op = mem::zeroed::<OPENFILENAMEW>();
+        op.lStructSize = mem::size_of::<OPENFILENAMEW>() as _;
+        op.hwndOwner = hwnd;
+        op.lpstrFile = file_name.as_mut_ptr();
+        op.nMaxFile = file_name.len() as _;
+        op.lpstrFilter = w!("pdhv log\0*.pdhl\0");
+        op.Flags = 0x00000800 | 0x00001000;
+        op.lpstrDefExt = w!("pdhl");
 
-impl InterpolatedCurve {
-    fn new(data: impl iter::Iterator<Item = f32>) -> Self {
-        let a = Vec::from_iter(data);
-        let n = a.len() - 1;
-
-        let mut l = vec![1.0_f32];
-        let mut u = vec![0.0_f32];
-        let mut z = vec![0.0_f32];
-
-        for i in 1..n {
-            l.push(4.0 - u[i - 1]);
-            u.push(1.0 / l[i]);
-            z.push(
-                ((3.0 * (a[i + 1] - a[i]) - 3.0 * (a[i] - a[i - 1])) - z[i - 1])
-                    / l[i],
+        if GetOpenFileNameW(&mut op) != 1 {
+            return;
+        }
+
+        let file_name_string = String::from_utf16(file_name.as_slice()).unwrap();
+
+        let replay = match source::replay::Replay::open(path::Path::new(
+            file_name_string.trim_matches(char::from(0)),
+        )) {
+            Some(replay) => replay,
+            None => return,
+        };
+
+        let mut collector = self.collector.lock().unwrap();
+
+        // the replayed paths are on a machine named after the log, add them to this source only
+        let source = collector.sources.len();
+        let paths = replay.paths().collect::<Vec<_>>();
+        self.replays.push((source, replay.playback()));
+        collector.sources.push(Box::new(replay));
+
+        for path in paths {
+            if let Some(hcounter) = collector.sources[source].add_counter(&path) {
+                collector.insert_counter(menu, CounterV2::with_source(path, source, hcounter));
+            }
+        }
+        self.publish(&collector);
+        drop(collector);
+
+        for id in [
+            menu::IDM_REPLAY_PAUSE,
+            menu::IDM_REPLAY_BACKWARD,
+            menu::IDM_REPLAY_FORWARD,
+        ] {
+            menu.set_item_state_by_id(Some(menu::IDM_REPLAY), id, None, false);
+        }
+        self.set_replay_speed(menu, 1.0);
+        self.pause_replay_state(menu, false);
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn pause_replay(&mut self, menu: &mut menu::Menu) {
+        let paused = !self
+            .replays
+            .iter()
+            .all(|(_, playback)| playback.lock().unwrap().paused);
+
+        self.pause_replay_state(menu, paused);
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn pause_replay_state(&mut self, menu: &mut menu::Menu, paused: bool) {
+        for (_, playback) in self.replays.iter() {
+            playback.lock().unwrap().paused = paused;
+        }
+
+        menu.set_item_state_by_id(
+            Some(menu::IDM_REPLAY),
+            menu::IDM_REPLAY_PAUSE,
+            Some(paused),
+            false,
+        );
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn set_replay_speed(&mut self, menu: &mut menu::Menu, speed: f64) {
+        for (_, playback) in self.replays.iter() {
+            playback.lock().unwrap().speed = speed;
+        }
+
+        for (id, item_speed) in [
+            (menu::IDM_REPLAY_SPEED_1, 1.0),
+            (menu::IDM_REPLAY_SPEED_2, 2.0),
+            (menu::IDM_REPLAY_SPEED_10, 10.0),
+        ] {
+            menu.set_item_state_by_id(
+                Some(menu::IDM_REPLAY),
+                id,
+                Some(item_speed == speed),
+                false,
             );
         }
-        l.push(1.0);
-        z.push(0.0);
+    }
 
-        let mut c = Vec::from_iter(iter::repeat(0.0_f32).take(n + 1));
-        let mut b = Vec::from_iter(iter::repeat(0.0_f32).take(n));
-        let mut d = Vec::from_iter(iter::repeat(0.0_f32).take(n));
+    pub fn seek_replay(&mut self, delta: f64) {
+        for (_, playback) in self.replays.iter() {
+            playback.lock().unwrap().seek(delta);
+        }
+    }
+}
+
+// the choices of the interval menu of a counter, by position
+pub fn counter_intervals() -> impl Iterator<Item = Option<Duration>> {
+    iter::once(None).chain(
+        menu::IDM_INTERVALS
+            .iter()
+            .map(|(_, interval)| Some(Duration::from_millis(*interval))),
+    )
+}
 
-        for j in (0..n).rev() {
-            c[j] = z[j] - u[j] * c[j + 1];
-            b[j] = (a[j + 1] - a[j]) - (c[j + 1] + 2.0 * c[j]) / 3.0;
-            d[j] = (c[j + 1] - c[j]) / 3.0;
+// a replay is closed with its last counter, the replay menu is grayed with the last replay
+#[allow(clippy::missing_safety_doc)]
+unsafe fn drop_unused_replays(
+    replays: &mut Vec<(usize, Arc<Mutex<source::replay::Playback>>)>,
+    menu: &mut menu::Menu,
+    collector: &mut Collector,
+) {
+    let unused = replays
+        .iter()
+        .map(|(source, _)| *source)
+        .filter(|source| !collector.counters.values().any(|counter| counter.source == *source))
+        .collect::<Vec<_>>();
+    if unused.is_empty() {
+        return;
+    }
+
+    // from the last, the indices of the others still hold
+    for source in unused.into_iter().rev() {
+        collector.remove_source(source);
+        replays.retain(|(replay, _)| *replay != source);
+        for (replay, _) in replays.iter_mut() {
+            if *replay > source {
+                *replay -= 1;
+            }
         }
+    }
 
-        Self { n, a, b, c, d }
+    if replays.is_empty() {
+        for (id, check) in [
+            (menu::IDM_REPLAY_PAUSE, Some(false)),
+            (menu::IDM_REPLAY_SPEED_1, Some(true)),
+            (menu::IDM_REPLAY_SPEED_2, Some(false)),
+            (menu::IDM_REPLAY_SPEED_10, Some(false)),
+            (menu::IDM_REPLAY_BACKWARD, None),
+            (menu::IDM_REPLAY_FORWARD, None),
+        ] {
+            menu.set_item_state_by_id(Some(menu::IDM_REPLAY), id, check, true);
+        }
     }
+}
 
-    pub fn interpolate(&self, x: f32, j: usize) -> f32 {
-        self.a[j]
-            + self.b[j] * (x - j as f32)
-            + self.c[j] * (x - j as f32).powf(2.0)
-            + self.d[j] * (x - j as f32).powf(3.0)
+#[allow(clippy::missing_safety_doc)]
+unsafe fn remove_counter_menus(menu: &mut menu::Menu, id: isize) {
+    menu.remove_item(
+        Some(menu::IDM_COUNTER_REMOVE),
+        id + menu::IDM_REMOVE_RANGE.start,
+    );
+    menu.remove_menu(
+        Some(menu::IDM_COUNTER_INTERVAL),
+        id + menu::IDM_COUNTER_INTERVAL_MENU_RANGE.start,
+    );
+}
+
+// the samples are stamped with their deadline rather than the time they were collected at
+unsafe fn write_log_line(mut hfile: &fs::File, timestamp: u64, line: &str) -> io::Result<()> {
+    // in 100 nanoseconds since 1601
+    let filetime = (timestamp + 11_644_473_600_000) * 10_000;
+    let filetime = FILETIME {
+        dwLowDateTime: filetime as u32,
+        dwHighDateTime: (filetime >> 32) as u32,
+    };
+
+    let mut utc_t: SYSTEMTIME = mem::zeroed();
+    let mut sys_t: SYSTEMTIME = mem::zeroed();
+    if FileTimeToSystemTime(&filetime, &mut utc_t) == 0
+        || SystemTimeToTzSpecificLocalTime(ptr::null(), &utc_t, &mut sys_t) == 0
+    {
+        GetLocalTime(&mut sys_t);
     }
+
+    writeln!(
+        hfile,
+        "D{}-{}-{} T{}:{}:{}.{} ; {}",
+        sys_t.wYear,
+        sys_t.wMonth,
+        sys_t.wDay,
+        sys_t.wHour,
+        sys_t.wMinute,
+        sys_t.wSecond,
+        sys_t.wMilliseconds,
+        line
+    )
 }
@@ -0,0 +1,271 @@
+use std::{
+    collections,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread, time,
+};
+
+pub fn now_millis() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+// the first deadline after now
+fn next_deadline(now: u64, tick: u64) -> u64 {
+    (now / tick + 1) * tick
+}
+
+// a deadline further than a tick away is from before the clock went backward
+fn anchor(next: u64, now: u64, tick: u64) -> u64 {
+    match next.saturating_sub(now) > tick {
+        true => next_deadline(now, tick),
+        false => next,
+    }
+}
+
+// deadlines are multiples of the tick since the unix epoch, a 1 s tick fires at :00.000, :01.000...
+pub struct Scheduler {
+    tx: mpsc::Sender<u64>,
+}
+
+impl Scheduler {
+    // on_tick gets the deadline in milliseconds since the unix epoch, and returns the tick to
+    // go on with
+    pub fn new(
+        tick: time::Duration,
+        mut on_tick: impl FnMut(u64) -> time::Duration + Send + 'static,
+    ) -> Self {
+        let mut tick = tick.as_millis().max(1) as u64;
+
+        // a new tick wakes the thread up, it is gone with the scheduler
+        let (tx, rx) = mpsc::channel::<u64>();
+        thread::spawn(move || {
+            let mut next = next_deadline(now_millis(), tick);
+
+            loop {
+                let now = now_millis();
+                next = anchor(next, now, tick);
+
+                // sleep can wake up early on some platforms
+                if let Some(remaining) = next.checked_sub(now).filter(|ms| *ms > 0) {
+                    match rx.recv_timeout(time::Duration::from_millis(remaining)) {
+                        Ok(new_tick) => {
+                            tick = new_tick;
+                            next = next_deadline(now_millis(), tick);
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    continue;
+                }
+
+                tick = on_tick(next).as_millis().max(1) as u64;
+
+                // the deadlines that went by while on_tick ran are skipped, not stretched
+                next = next_deadline(now_millis().max(next), tick);
+            }
+        });
+
+        Self { tx }
+    }
+
+    // taken into account right away, not after the current deadline
+    pub fn set_tick(&self, tick: time::Duration) {
+        let _ = self.tx.send(tick.as_millis().max(1) as u64);
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+// the greatest common divisor of the intervals, the fallback without any
+pub fn tick(
+    intervals: impl Iterator<Item = time::Duration>,
+    fallback: time::Duration,
+) -> time::Duration {
+    time::Duration::from_millis(
+        intervals
+            .map(|interval| interval.as_millis() as u64)
+            .reduce(gcd)
+            .unwrap_or(fallback.as_millis() as u64)
+            .max(1),
+    )
+}
+
+// counters sharing an interval are collected together, with the deadline of their last
+// collection in milliseconds since the unix epoch, None until the first collection
+#[derive(Default)]
+pub struct Groups {
+    last_deadlines: collections::BTreeMap<time::Duration, Option<u64>>,
+}
+
+impl Groups {
+    // a group is due when the deadline is a multiple of its interval, with the deadline of its
+    // previous collection, an earlier deadline is after the clock went backward
+    pub fn due(
+        &mut self,
+        intervals: &collections::BTreeSet<time::Duration>,
+        deadline: u64,
+    ) -> Vec<(time::Duration, Option<u64>)> {
+        self.last_deadlines
+            .retain(|interval, _| intervals.contains(interval));
+        for interval in intervals.iter() {
+            self.last_deadlines.entry(*interval).or_insert(None);
+        }
+
+        self.last_deadlines
+            .iter_mut()
+            .filter(|(interval, last_deadline)| match last_deadline {
+                Some(last_deadline) => {
+                    deadline != *last_deadline
+                        && deadline.is_multiple_of(interval.as_millis() as u64)
+                }
+                None => true,
+            })
+            .map(|(interval, last_deadline)| (*interval, last_deadline.replace(deadline)))
+            .collect()
+    }
+}
+
+// the deadlines between the previous collection of each due group and this one, in order
+pub fn missed(due: &[(time::Duration, Option<u64>)], deadline: u64) -> Vec<(u64, time::Duration)> {
+    let mut missed = due
+        .iter()
+        .filter_map(|(interval, previous)| {
+            let interval_ms = interval.as_millis() as u64;
+
+            previous.map(|previous| {
+                ((previous / interval_ms + 1) * interval_ms..deadline)
+                    .step_by(interval_ms as usize)
+                    .map(move |missed| (missed, *interval))
+            })
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    missed.sort_by_key(|(missed, _)| *missed);
+
+    missed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    fn intervals(millis: &[u64]) -> collections::BTreeSet<time::Duration> {
+        millis
+            .iter()
+            .map(|millis| time::Duration::from_millis(*millis))
+            .collect()
+    }
+
+    #[test]
+    fn tick_is_the_gcd_of_the_intervals() {
+        let fallback = time::Duration::from_secs(1);
+
+        assert_eq!(
+            tick(intervals(&[1000, 1500]).into_iter(), fallback),
+            time::Duration::from_millis(500)
+        );
+        assert_eq!(tick(intervals(&[]).into_iter(), fallback), fallback);
+    }
+
+    #[test]
+    fn groups_are_due_on_multiples_of_their_interval() {
+        let mut groups = Groups::default();
+        let intervals = intervals(&[1000, 5000]);
+        let due = |groups: &mut Groups, deadline| {
+            groups
+                .due(&intervals, deadline)
+                .into_iter()
+                .map(|(interval, _)| interval.as_millis() as u64)
+                .collect::<Vec<_>>()
+        };
+
+        // every group the first time
+        assert_eq!(due(&mut groups, 3000), vec![1000, 5000]);
+        assert_eq!(due(&mut groups, 4000), vec![1000]);
+        assert_eq!(due(&mut groups, 5000), vec![1000, 5000]);
+        // the same deadline twice
+        assert_eq!(due(&mut groups, 5000), Vec::<u64>::new());
+        assert_eq!(due(&mut groups, 6000), vec![1000]);
+    }
+
+    #[test]
+    fn missed_deadlines_are_counted_in_order() {
+        let mut groups = Groups::default();
+        let intervals = intervals(&[1000, 2000]);
+        groups.due(&intervals, 2000);
+
+        let due = groups.due(&intervals, 6000);
+        assert_eq!(
+            missed(&due, 6000),
+            vec![
+                (3000, time::Duration::from_millis(1000)),
+                (4000, time::Duration::from_millis(1000)),
+                (4000, time::Duration::from_millis(2000)),
+                (5000, time::Duration::from_millis(1000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn clock_going_backward_misses_nothing() {
+        let mut groups = Groups::default();
+        let intervals = intervals(&[1000]);
+        groups.due(&intervals, 3_600_000);
+
+        // an hour back, the group is due again right away
+        let due = groups.due(&intervals, 5000);
+        assert_eq!(due, vec![(time::Duration::from_millis(1000), Some(3_600_000))]);
+        assert!(missed(&due, 5000).is_empty());
+    }
+
+    #[test]
+    fn clock_going_backward_is_anchored_again() {
+        assert_eq!(anchor(2000, 1500, 1000), 2000);
+        assert_eq!(anchor(2000, 2500, 1000), 2000);
+        // an hour back
+        assert_eq!(anchor(3_602_000, 1500, 1000), 2000);
+    }
+
+    #[test]
+    fn deadlines_are_multiples_of_the_tick() {
+        let deadlines = Arc::new(Mutex::new(Vec::new()));
+        let thread_deadlines = deadlines.clone();
+        let scheduler = Scheduler::new(time::Duration::from_millis(20), move |deadline| {
+            thread_deadlines.lock().unwrap().push(deadline);
+            time::Duration::from_millis(20)
+        });
+
+        thread::sleep(time::Duration::from_millis(200));
+        drop(scheduler);
+
+        let deadlines = deadlines.lock().unwrap();
+        assert!(deadlines.len() >= 5);
+        assert!(deadlines.iter().all(|deadline| deadline % 20 == 0));
+        assert!(deadlines.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn new_tick_is_used_right_away() {
+        let deadlines = Arc::new(Mutex::new(Vec::new()));
+        let thread_deadlines = deadlines.clone();
+        let scheduler = Scheduler::new(time::Duration::from_secs(3600), move |deadline| {
+            thread_deadlines.lock().unwrap().push(deadline);
+            time::Duration::from_millis(20)
+        });
+
+        let start = now_millis();
+        scheduler.set_tick(time::Duration::from_millis(20));
+        thread::sleep(time::Duration::from_millis(200));
+
+        let deadlines = deadlines.lock().unwrap();
+        assert!(deadlines.first().is_some_and(|first| first - start <= 40));
+    }
+}
@@ -0,0 +1,192 @@
+pub mod agent;
+pub mod cgroup;
+pub mod derived;
+pub mod diskstats;
+pub mod exec;
+pub mod hwmon;
+pub mod meminfo;
+pub mod net;
+#[cfg(windows)]
+pub mod pdh;
+pub mod pressure;
+pub mod proc_stat;
+pub mod process;
+pub mod prometheus;
+pub mod replay;
+pub mod statsd;
+pub mod synthetic;
+pub mod tail;
+
+use serde::de::DeserializeOwned;
+
+use std::{collections, env, fs, path, time};
+
+use super::counter_path::{self, CounterPath};
+
+pub trait CounterSource: Send {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize>;
+
+    fn remove_counter(&mut self, hcounter: usize);
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool;
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>>;
+
+    // in milliseconds since the unix epoch, when the values were taken if not at the collection
+    fn timestamp(&self) -> Option<u64> {
+        None
+    }
+
+    // of the sources given to derive, the ones it reads, they are collected along with it
+    fn reads(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    // computed from the values the other sources just collected, see derived::Derived
+    fn derive(&mut self, _sources: &[Box<dyn CounterSource>], _collected: &[bool]) {}
+
+    // the counters it can add, offered in the Counter > Add menu
+    fn offers(&self) -> Vec<CounterPath> {
+        Vec::new()
+    }
+
+    fn close(&mut self) {}
+}
+
+#[allow(clippy::missing_safety_doc, clippy::vec_init_then_push)]
+pub unsafe fn default_sources() -> Vec<Box<dyn CounterSource>> {
+    let mut sources: Vec<Box<dyn CounterSource>> = Vec::new();
+
+    // endpoints to scrape, as a comma separated list of http urls
+    for url in env::var("PDHV_SCRAPE").unwrap_or_default().split(',') {
+        if let Some(prometheus) = prometheus::Prometheus::new(url.trim()) {
+            sources.push(Box::new(prometheus));
+        }
+    }
+
+    // counters defined by a command, see exec::Definition
+    if let Some(exec) = env::current_dir()
+        .ok()
+        .and_then(|dir| load_definitions(&dir.join("exec.json")))
+        .map(exec::Exec::new)
+    {
+        sources.push(Box::new(exec));
+    }
+
+    // log files followed for matching lines, see tail::Definition
+    if let Some(tail) = env::current_dir()
+        .ok()
+        .and_then(|dir| load_definitions(&dir.join("tail.json")))
+        .map(tail::Tail::new)
+    {
+        sources.push(Box::new(tail));
+    }
+
+    // port or address:port to listen on for statsd packets
+    if let Some(statsd) = env::var("PDHV_STATSD")
+        .ok()
+        .and_then(|address| statsd::Statsd::bind(address.trim()))
+    {
+        sources.push(Box::new(statsd));
+    }
+
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(proc_stat::ProcStat::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(meminfo::Memory::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(diskstats::PhysicalDisk::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(net::NetworkInterface::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(net::Tcpv4::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(process::Process::new("/proc")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(cgroup::Cgroup::new("/sys/fs/cgroup")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(hwmon::Sensor::new("/sys/class/hwmon")));
+    #[cfg(target_os = "linux")]
+    sources.push(Box::new(pressure::Pressure::new("/proc")));
+
+    // instances[,churn[,seed]] of the synthetic counters, only when asked for
+    if let Some(synthetic) = env::var("PDHV_SYNTHETIC")
+        .ok()
+        .and_then(|spec| synthetic::Synthetic::parse(&spec))
+    {
+        sources.push(Box::new(synthetic));
+    }
+
+    #[cfg(windows)]
+    sources.push(Box::new(pdh::Pdh::open()));
+
+    sources
+}
+
+// a json array of definitions, see exec::Definition
+pub fn load_definitions<T: DeserializeOwned>(file: &path::Path) -> Option<Vec<T>> {
+    serde_json::from_str(&fs::read_to_string(file).ok()?)
+        .map_err(|err| eprintln!("Unable to load {} err({})", file.display(), err))
+        .ok()
+}
+
+// counts per second over the interval, and zero for the names seen before without counts
+pub fn per_second(
+    seen: &mut collections::BTreeSet<String>,
+    counts: &collections::HashMap<String, f64>,
+    elapsed: time::Duration,
+) -> Option<Vec<(String, f64)>> {
+    let elapsed = elapsed.as_secs_f64();
+    seen.extend(counts.keys().cloned());
+
+    (elapsed > 0.0).then(|| {
+        seen.iter()
+            .map(|name| {
+                let count = counts.get(name).copied().unwrap_or(0.0);
+                (name.clone(), count / elapsed)
+            })
+            .collect()
+    })
+}
+
+// every instance of the named counters of an object
+pub fn paths_of<'a>(object: &str, counters: impl Iterator<Item = &'a str>) -> Vec<CounterPath> {
+    counters
+        .filter_map(|counter| CounterPath::parse(&format!(r"\{}\{}", object, counter)))
+        .collect()
+}
+
+pub fn filter_instances(
+    instance: Option<&str>,
+    values: impl Iterator<Item = (String, f64)>,
+) -> Vec<(String, f64)> {
+    match instance {
+        None => values.collect(),
+        Some(pattern) => values
+            .filter(|(name, _)| counter_path::matches(pattern, name))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_seen_before_count_zero() {
+        let mut seen = collections::BTreeSet::new();
+        let counts = collections::HashMap::from([("a".to_string(), 4.0)]);
+
+        assert_eq!(per_second(&mut seen, &counts, time::Duration::ZERO), None);
+        assert_eq!(
+            per_second(&mut seen, &counts, time::Duration::from_secs(2)),
+            Some(vec![("a".to_string(), 2.0)])
+        );
+
+        let counts = collections::HashMap::from([("b".to_string(), 1.0)]);
+        assert_eq!(
+            per_second(&mut seen, &counts, time::Duration::from_secs(1)),
+            Some(vec![("a".to_string(), 0.0), ("b".to_string(), 1.0)])
+        );
+    }
+}
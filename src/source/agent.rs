@@ -0,0 +1,596 @@
+// Agent protocol, over a plain TCP stream in both directions.
+//
+// Every message is a frame: a u32 big endian payload length followed by the payload, a
+// JSON encoded `Message`. On connection the agent sends `Hello` with the counter paths it
+// was started with, then a `Sample` after each collection. The viewer sends `Add` and
+// `Remove` at any time to change the counters it receives, `id` is chosen by the viewer.
+//
+// {"Hello":{"paths":["\\Processor(*)\\% Processor Time"]}}
+// {"Add":{"id":1,"path":"\\Processor(*)\\% Processor Time"}}
+// {"Sample":{"timestamp":1676365503450,"values":[[1,[["0",3.5],["_Total",2.0]]]]}}
+//
+// The viewer connects again after losing the agent and adds its counters back, with the
+// same ids.
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections,
+    io::{self, Read, Write},
+    mem,
+    net::{self, ToSocketAddrs},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread, time,
+};
+
+use super::{super::schedule, CounterPath, CounterSource};
+
+pub const DEFAULT_PORT: u16 = 7000;
+
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+const RECONNECT_INTERVAL: time::Duration = time::Duration::from_secs(2);
+
+pub type Instances = Vec<(String, f64)>;
+
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    Hello {
+        paths: Vec<CounterPath>,
+    },
+    Add {
+        id: usize,
+        path: CounterPath,
+    },
+    Remove {
+        id: usize,
+    },
+    // milliseconds since the unix epoch, values are None when the counter has no data
+    Sample {
+        timestamp: u64,
+        values: Vec<(usize, Option<Instances>)>,
+    },
+}
+
+pub fn write_message(stream: &mut impl Write, message: &Message) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+
+    stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+    stream.write_all(&payload)?;
+    stream.flush()
+}
+
+pub fn read_message(stream: &mut impl Read) -> io::Result<Message> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+// of every viewer, its stream and the (source, hcounter) of its counters by id
+type Viewers = collections::HashMap<
+    usize,
+    (
+        net::TcpStream,
+        collections::HashMap<usize, Option<(usize, usize)>>,
+    ),
+>;
+
+// what a viewer did, for the collecting thread of the agent
+enum Event {
+    Connected(usize, net::TcpStream),
+    Received(usize, Message),
+    Closed(usize),
+    // a deadline of the scheduler, in milliseconds since the unix epoch
+    Tick(u64),
+}
+
+// the agent side, every viewer connected shares the sources and is sent the counters it added
+pub fn serve(
+    listener: net::TcpListener,
+    sources: Vec<Box<dyn CounterSource>>,
+    paths: Vec<CounterPath>,
+    interval: time::Duration,
+) {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || collect_loop(rx, sources));
+
+    // on the same deadlines as the viewer, the scheduler is gone with the agent
+    let tick_tx = tx.clone();
+    let _scheduler = schedule::Scheduler::new(interval, move |deadline| {
+        let _ = tick_tx.send(Event::Tick(deadline));
+        interval
+    });
+
+    for (connection, stream) in listener.incoming().flatten().enumerate() {
+        let (tx, paths) = (tx.clone(), paths.clone());
+        thread::spawn(move || {
+            let peer = stream.peer_addr();
+            if let Err(err) = accept(connection, stream, paths, &tx) {
+                eprintln!("Connection with {:?} closed err({})", peer, err);
+            }
+
+            let _ = tx.send(Event::Closed(connection));
+        });
+    }
+}
+
+fn accept(
+    connection: usize,
+    mut stream: net::TcpStream,
+    paths: Vec<CounterPath>,
+    tx: &mpsc::Sender<Event>,
+) -> io::Result<()> {
+    write_message(&mut stream, &Message::Hello { paths })?;
+
+    // a viewer that stopped reading must not hold the others
+    let writer = stream.try_clone()?;
+    writer.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    let _ = tx.send(Event::Connected(connection, writer));
+
+    loop {
+        let message = read_message(&mut stream)?;
+        let _ = tx.send(Event::Received(connection, message));
+    }
+}
+
+fn collect_loop(rx: mpsc::Receiver<Event>, mut sources: Vec<Box<dyn CounterSource>>) {
+    let mut connections = Viewers::new();
+    let mut last_update = time::Instant::now();
+
+    // the viewers are handled between the collections
+    for event in rx {
+        let (message, counters) = match event {
+            Event::Connected(connection, stream) => {
+                connections.insert(connection, (stream, collections::HashMap::new()));
+                continue;
+            }
+            Event::Received(connection, message) => match connections.get_mut(&connection) {
+                Some((_, counters)) => (message, counters),
+                None => continue,
+            },
+            Event::Closed(connection) => {
+                for (source, hcounter) in connections
+                    .remove(&connection)
+                    .into_iter()
+                    .flat_map(|(_, counters)| counters.into_values().flatten())
+                {
+                    sources[source].remove_counter(hcounter);
+                }
+                continue;
+            }
+            Event::Tick(deadline) => {
+                collect(
+                    &mut sources,
+                    &mut connections,
+                    deadline,
+                    last_update.elapsed(),
+                );
+                last_update = time::Instant::now();
+                continue;
+            }
+        };
+
+        let removed = match message {
+            Message::Add { id, path } => {
+                let counter =
+                    sources
+                        .iter_mut()
+                        .enumerate()
+                        .find_map(|(source, counter_source)| {
+                            counter_source
+                                .add_counter(&path)
+                                .map(|hcounter| (source, hcounter))
+                        });
+                counters.insert(id, counter)
+            }
+            Message::Remove { id } => counters.remove(&id),
+            _ => None,
+        };
+
+        if let Some(Some((source, hcounter))) = removed {
+            sources[source].remove_counter(hcounter);
+        }
+    }
+}
+
+// the sample of every viewer is stamped with the deadline
+fn collect(
+    sources: &mut [Box<dyn CounterSource>],
+    connections: &mut Viewers,
+    timestamp: u64,
+    elapsed: time::Duration,
+) {
+    let collected = sources
+        .iter_mut()
+        .map(|counter_source| counter_source.collect(elapsed))
+        .collect::<Vec<_>>();
+
+    for (stream, counters) in connections.values_mut() {
+        let values = counters
+            .iter()
+            .map(|(id, counter)| {
+                let values = counter
+                    .filter(|(source, _)| collected[*source])
+                    .and_then(|(source, hcounter)| sources[source].get_values(hcounter))
+                    .map(|values| {
+                        // json has no NaN or infinity
+                        values
+                            .into_iter()
+                            .filter(|(_, value)| value.is_finite())
+                            .collect()
+                    });
+
+                (*id, values)
+            })
+            .collect();
+
+        // its reader stops too and the viewer is closed
+        if write_message(stream, &Message::Sample { timestamp, values }).is_err() {
+            let _ = stream.shutdown(net::Shutdown::Both);
+        }
+    }
+}
+
+// the write half of the current connection to the agent, and what it sent
+#[derive(Default)]
+struct Connection {
+    // None while disconnected
+    stream: Option<net::TcpStream>,
+    // every counter added, without the machine, to add them again on reconnection
+    counters: collections::HashMap<usize, CounterPath>,
+    sample: Option<(u64, collections::HashMap<usize, Option<Instances>>)>,
+}
+
+// connects and reads the hello of the agent
+fn handshake(address: &str) -> Option<(net::TcpStream, Vec<CounterPath>)> {
+    let mut stream =
+        net::TcpStream::connect_timeout(&address.to_socket_addrs().ok()?.next()?, CONNECT_TIMEOUT)
+            .ok()?;
+
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).ok()?;
+    let paths = match read_message(&mut stream).ok()? {
+        Message::Hello { paths } => paths,
+        _ => return None,
+    };
+    stream.set_read_timeout(None).ok()?;
+
+    Some((stream, paths))
+}
+
+// until the agent closes the connection
+fn receive(mut stream: net::TcpStream, connection: &Mutex<Connection>) {
+    {
+        let mut connection = connection.lock().unwrap();
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_) => return,
+        };
+
+        for (id, path) in connection.counters.iter() {
+            let message = Message::Add {
+                id: *id,
+                path: path.clone(),
+            };
+            if write_message(&mut writer, &message).is_err() {
+                return;
+            }
+        }
+
+        connection.stream = Some(writer);
+    }
+
+    while let Ok(message) = read_message(&mut stream) {
+        if let Message::Sample { timestamp, values } = message {
+            connection.lock().unwrap().sample = Some((timestamp, values.into_iter().collect()));
+        }
+    }
+
+    let mut connection = connection.lock().unwrap();
+    connection.stream = None;
+    connection.sample = None;
+}
+
+pub struct Agent {
+    address: String,
+    paths: Vec<CounterPath>,
+    _tx: mpsc::Sender<()>,
+    connection: Arc<Mutex<Connection>>,
+    curr: collections::HashMap<usize, Option<Instances>>,
+    // of the current sample, on the clock of the agent
+    timestamp: Option<u64>,
+    last_id: usize,
+}
+
+impl Agent {
+    // keeps connecting to the agent while it is unreachable
+    pub fn connect(address: &str) -> Option<Self> {
+        if address.is_empty() {
+            return None;
+        }
+
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+
+        // the paths to announce come from the first connection
+        let mut first = handshake(&address);
+        let paths = match first.as_mut() {
+            Some((_, paths)) => mem::take(paths),
+            None => {
+                eprintln!("Unable to connect to agent {}, retrying", address);
+                Vec::new()
+            }
+        };
+
+        let connection = Arc::new(Mutex::new(Connection::default()));
+        let thread_connection = connection.clone();
+        let thread_address = address.clone();
+
+        let (_tx, rx) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let mut handshaken = first;
+            loop {
+                if let Some((stream, _)) = handshaken {
+                    receive(stream, &thread_connection);
+                }
+
+                match rx.recv_timeout(RECONNECT_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => (),
+                    _ => break,
+                }
+
+                handshaken = handshake(&thread_address);
+            }
+        });
+
+        Some(Self {
+            address,
+            paths,
+            _tx,
+            connection,
+            curr: collections::HashMap::new(),
+            timestamp: None,
+            last_id: 0,
+        })
+    }
+
+    // the counters the agent was started with, on a machine named after its address
+    pub fn paths(&self) -> impl Iterator<Item = CounterPath> + '_ {
+        self.paths
+            .iter()
+            .map(|path| path.with_machine(Some(&self.address)))
+    }
+}
+
+impl CounterSource for Agent {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.machine.as_deref() != Some(self.address.as_str()) {
+            return None;
+        }
+
+        self.last_id += 1;
+        let path = path.with_machine(None);
+        let mut connection = self.connection.lock().unwrap();
+
+        // a failed write is seen by the reader, the counter is added on reconnection
+        if let Some(stream) = connection.stream.as_mut() {
+            let message = Message::Add {
+                id: self.last_id,
+                path: path.clone(),
+            };
+            let _ = write_message(stream, &message);
+        }
+        connection.counters.insert(self.last_id, path);
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.curr.remove(&hcounter);
+
+        let mut connection = self.connection.lock().unwrap();
+        connection.counters.remove(&hcounter);
+        if let Some(stream) = connection.stream.as_mut() {
+            let _ = write_message(stream, &Message::Remove { id: hcounter });
+        }
+    }
+
+    fn offers(&self) -> Vec<CounterPath> {
+        self.paths().collect()
+    }
+
+    // keep the last sample until the agent sends a new one
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        let mut connection = self.connection.lock().unwrap();
+
+        if let Some((timestamp, sample)) = connection.sample.take() {
+            self.timestamp = Some(timestamp);
+            self.curr = sample;
+        }
+
+        if connection.stream.is_none() {
+            self.curr.clear();
+        }
+
+        connection.stream.is_some()
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        self.curr.get(&hcounter)?.clone()
+    }
+
+    fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    fn close(&mut self) {
+        if let Some(stream) = self.connection.lock().unwrap().stream.as_ref() {
+            let _ = stream.shutdown(net::Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the number of counters it holds, for every counter
+    #[derive(Default)]
+    struct Fake {
+        counters: Arc<Mutex<usize>>,
+    }
+
+    impl CounterSource for Fake {
+        fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+            (path.object == "Fake").then(|| {
+                *self.counters.lock().unwrap() += 1;
+                0
+            })
+        }
+
+        fn remove_counter(&mut self, _hcounter: usize) {
+            *self.counters.lock().unwrap() -= 1;
+        }
+
+        fn collect(&mut self, _elapsed: time::Duration) -> bool {
+            true
+        }
+
+        fn get_values(&self, _hcounter: usize) -> Option<Vec<(String, f64)>> {
+            Some(vec![(
+                "a".to_string(),
+                *self.counters.lock().unwrap() as f64,
+            )])
+        }
+    }
+
+    // polls the collection until the values are the expected ones
+    fn wait_for(agent: &mut Agent, hcounter: usize, expected: Option<f64>) -> bool {
+        (0..100).any(|_| {
+            thread::sleep(time::Duration::from_millis(20));
+            agent.collect(time::Duration::ZERO);
+
+            agent.get_values(hcounter).map(|values| values[0].1) == expected
+        })
+    }
+
+    #[test]
+    fn viewers_share_the_sources() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let path = CounterPath::parse(r"\Fake\Value").unwrap();
+        let fake = Fake::default();
+        let counters = fake.counters.clone();
+
+        let paths = vec![path.clone()];
+        thread::spawn(move || {
+            serve(
+                listener,
+                vec![Box::new(fake)],
+                paths,
+                time::Duration::from_millis(10),
+            )
+        });
+
+        let mut first = Agent::connect(&address).unwrap();
+        let mut second = Agent::connect(&address).unwrap();
+        assert_eq!(
+            first.paths().collect::<Vec<_>>(),
+            vec![path.with_machine(Some(&address))]
+        );
+
+        let path = path.with_machine(Some(&address));
+        let first_counter = first.add_counter(&path).unwrap();
+        let second_counter = second.add_counter(&path).unwrap();
+        assert!(wait_for(&mut first, first_counter, Some(2.0)));
+        assert!(wait_for(&mut second, second_counter, Some(2.0)));
+        // stamped with the deadline, a multiple of the interval
+        assert!(first.timestamp().is_some_and(|timestamp| timestamp % 10 == 0));
+
+        // the counters of a viewer leave with it
+        second.close();
+        drop(second);
+        assert!(wait_for(&mut first, first_counter, Some(1.0)));
+        assert_eq!(*counters.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn viewer_connects_again() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let path = CounterPath::parse(r"\Fake\Value").unwrap();
+
+        // a single counter added on every connection, its value is the connection number
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                write_message(&mut stream, &Message::Hello { paths: Vec::new() }).unwrap();
+
+                let id = match read_message(&mut stream).unwrap() {
+                    Message::Add { id, path } => {
+                        tx.send(path).unwrap();
+                        id
+                    }
+                    _ => panic!("Add expected"),
+                };
+                let values = vec![(id, Some(vec![("a".to_string(), connection as f64)]))];
+                write_message(
+                    &mut stream,
+                    &Message::Sample {
+                        timestamp: 1000,
+                        values,
+                    },
+                )
+                .unwrap();
+
+                // the second one stays
+                if connection == 0 {
+                    let _ = read_message(&mut stream);
+                } else {
+                    thread::sleep(time::Duration::from_secs(60));
+                }
+            }
+        });
+
+        let mut agent = Agent::connect(&address).unwrap();
+        let hcounter = agent
+            .add_counter(&path.with_machine(Some(&address)))
+            .unwrap();
+        assert!(wait_for(&mut agent, hcounter, Some(0.0)));
+        assert_eq!(rx.recv().unwrap(), path);
+
+        // the agent goes away, no data until it is back
+        agent
+            .connection
+            .lock()
+            .unwrap()
+            .stream
+            .as_ref()
+            .unwrap()
+            .shutdown(net::Shutdown::Both)
+            .unwrap();
+        assert!(wait_for(&mut agent, hcounter, None));
+        assert!(!agent.collect(time::Duration::ZERO));
+
+        assert_eq!(rx.recv_timeout(RECONNECT_INTERVAL * 2).unwrap(), path);
+        assert!(wait_for(&mut agent, hcounter, Some(1.0)));
+    }
+}
@@ -0,0 +1,281 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{pressure, CounterPath, CounterSource};
+
+const ROOT_INSTANCE: &str = "_Root";
+
+const COUNTERS: [&str; 9] = [
+    "% Processor Time",
+    "Memory Current Bytes",
+    "IO Read Bytes/sec",
+    "IO Write Bytes/sec",
+    "% CPU Some Pressure",
+    "% Memory Some Pressure",
+    "% Memory Full Pressure",
+    "% IO Some Pressure",
+    "% IO Full Pressure",
+];
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    usage_usec: Option<u64>,
+    memory: Option<u64>,
+    io: Option<(u64, u64)>,
+    // cpu some, memory some, memory full, io some, io full avg10
+    pressure: [Option<f64>; 5],
+}
+
+pub struct Cgroup {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: collections::HashMap<String, Stats>,
+    curr: Vec<(String, Stats)>,
+    elapsed: f64,
+}
+
+impl Cgroup {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: collections::HashMap::new(),
+            curr: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read_cgroup(dir: &path::Path) -> Stats {
+        let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+
+        let usage_usec = read("cpu.stat").and_then(|cpu_stat| {
+            cpu_stat
+                .lines()
+                .find_map(|line| line.strip_prefix("usage_usec "))
+                .and_then(|value| value.trim().parse().ok())
+        });
+
+        let memory = read("memory.current").and_then(|value| value.trim().parse().ok());
+
+        // one line per device: "8:0 rbytes=1 wbytes=2 rios=3 wios=4 dbytes=5 dios=6"
+        let io = read("io.stat").map(|io_stat| {
+            io_stat
+                .split_whitespace()
+                .filter_map(|field| field.split_once('='))
+                .fold((0, 0), |(rbytes, wbytes), (name, value)| {
+                    let value = value.parse::<u64>().unwrap_or(0);
+                    match name {
+                        "rbytes" => (rbytes + value, wbytes),
+                        "wbytes" => (rbytes, wbytes + value),
+                        _ => (rbytes, wbytes),
+                    }
+                })
+        });
+
+        let avg10 = |file: &str, kind: &str| Some(pressure::parse(&read(file)?, kind)?.avg10);
+
+        Stats {
+            usage_usec,
+            memory,
+            io,
+            pressure: [
+                avg10("cpu.pressure", "some"),
+                avg10("memory.pressure", "some"),
+                avg10("memory.pressure", "full"),
+                avg10("io.pressure", "some"),
+                avg10("io.pressure", "full"),
+            ],
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, Stats)>> {
+        if !self.root.is_dir() {
+            return None;
+        }
+
+        let mut cgroups = Vec::new();
+
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            let name = match dir.strip_prefix(&self.root).ok()?.to_string_lossy() {
+                name if name.is_empty() => ROOT_INSTANCE.to_string(),
+                name => name.replace('\\', "/"),
+            };
+            cgroups.push((name, Self::read_cgroup(&dir)));
+
+            let mut children = match fs::read_dir(&dir) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                    .map(|entry| entry.path())
+                    .collect::<Vec<_>>(),
+                Err(_) => continue,
+            };
+            children.sort_unstable();
+
+            dirs.extend(children.into_iter().rev());
+        }
+
+        Some(cgroups)
+    }
+}
+
+impl CounterSource for Cgroup {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Cgroup" {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        if self.counters.is_empty() {
+            return true;
+        }
+
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr).into_iter().collect();
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        if self.prev.is_empty() || self.elapsed <= 0.0 {
+            return None;
+        }
+
+        // an instance without the matching controller enabled has no value
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let prev = self.prev.get(name)?;
+
+                let value = match counter {
+                    0 => {
+                        100.0 * curr.usage_usec?.saturating_sub(prev.usage_usec?) as f64
+                            / 1_000_000.0
+                            / self.elapsed
+                    }
+                    1 => curr.memory? as f64,
+                    2 => curr.io?.0.saturating_sub(prev.io?.0) as f64 / self.elapsed,
+                    3 => curr.io?.1.saturating_sub(prev.io?.1) as f64 / self.elapsed,
+                    counter => curr.pressure[counter - 4]?,
+                };
+
+                Some((name.clone(), value))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    fn write_stats(dir: &path::Path, usage_usec: u64, rbytes: u64, wbytes: u64) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("cpu.stat"),
+            format!("usage_usec {}\nuser_usec 0\nsystem_usec 0\n", usage_usec),
+        )
+        .unwrap();
+        fs::write(dir.join("memory.current"), "4096\n").unwrap();
+        fs::write(
+            dir.join("io.stat"),
+            format!(
+                "8:0 rbytes={} wbytes={} rios=1 wios=1 dbytes=0 dios=0\n\
+                 8:16 rbytes={} wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n",
+                rbytes, wbytes, rbytes
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("memory.pressure"),
+            "some avg10=1.50 avg60=1.00 avg300=0.50 total=100\n\
+             full avg10=0.25 avg60=0.00 avg300=0.00 total=10\n",
+        )
+        .unwrap();
+    }
+
+    fn values(cgroup: &mut Cgroup, path: &str) -> Option<Vec<(String, f64)>> {
+        let hcounter = cgroup.add_counter(&CounterPath::parse(path).unwrap())?;
+        let values = cgroup.get_values(hcounter);
+        cgroup.remove_counter(hcounter);
+
+        values
+    }
+
+    #[test]
+    fn reads_a_cgroup_tree() {
+        let root = env::temp_dir().join(format!("pdhv-cgroup-{}", process::id()));
+        let slice = root.join("system.slice");
+        write_stats(&root, 1_000_000, 0, 0);
+        // no io controller in the slice
+        write_stats(&slice, 500_000, 0, 0);
+        fs::remove_file(slice.join("io.stat")).unwrap();
+
+        let mut cgroup = Cgroup::new(&root);
+        let hcounter = cgroup
+            .add_counter(&CounterPath::parse(r"\Cgroup(*)\% Processor Time").unwrap())
+            .unwrap();
+        assert!(cgroup.collect(time::Duration::from_secs(1)));
+        assert_eq!(cgroup.get_values(hcounter), None);
+
+        write_stats(&root, 2_000_000, 1000, 500);
+        write_stats(&slice, 1_000_000, 0, 0);
+        fs::remove_file(slice.join("io.stat")).unwrap();
+        assert!(cgroup.collect(time::Duration::from_secs(2)));
+
+        // a second of cpu over two seconds
+        assert_eq!(
+            cgroup.get_values(hcounter),
+            Some(vec![
+                (ROOT_INSTANCE.to_string(), 50.0),
+                ("system.slice".to_string(), 25.0)
+            ])
+        );
+        // summed over the devices
+        assert_eq!(
+            values(&mut cgroup, r"\Cgroup(*)\IO Read Bytes/sec"),
+            Some(vec![(ROOT_INSTANCE.to_string(), 1000.0)])
+        );
+        assert_eq!(
+            values(&mut cgroup, r"\Cgroup(_Root)\IO Write Bytes/sec"),
+            Some(vec![(ROOT_INSTANCE.to_string(), 250.0)])
+        );
+        assert_eq!(
+            values(&mut cgroup, r"\Cgroup(system.slice)\Memory Current Bytes"),
+            Some(vec![("system.slice".to_string(), 4096.0)])
+        );
+        assert_eq!(
+            values(&mut cgroup, r"\Cgroup(_Root)\% Memory Full Pressure"),
+            Some(vec![(ROOT_INSTANCE.to_string(), 0.25)])
+        );
+        // no cpu.pressure in the tree
+        assert_eq!(
+            values(&mut cgroup, r"\Cgroup(*)\% CPU Some Pressure"),
+            Some(Vec::new())
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
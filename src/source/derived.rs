@@ -0,0 +1,481 @@
+use serde::Deserialize;
+
+use std::{collections, iter, str, time};
+
+use super::{CounterPath, CounterSource};
+
+type Instances = Vec<(String, f64)>;
+
+// 100 * "\Memory\Committed Bytes" / "\Memory\Commit Limit"
+// sum("\Processor(*)\% Processor Time") - "\Processor(_Total)\% Processor Time"
+#[derive(Deserialize)]
+pub struct Definition {
+    pub name: String,
+    pub expression: String,
+}
+
+enum Function {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+enum Expression {
+    Number(f64),
+    // (source, hcounter), None when no source has the counter
+    Counter(Option<(usize, usize)>),
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>),
+    Call(Function, Box<Expression>),
+}
+
+struct Parser<'a> {
+    chars: iter::Peekable<str::Chars<'a>>,
+    sources: &'a mut [Box<dyn CounterSource>],
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, expected: char) -> bool {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Option<Expression> {
+        let mut lhs = self.product()?;
+
+        loop {
+            let op = match () {
+                _ if self.eat('+') => '+',
+                _ if self.eat('-') => '-',
+                _ => return Some(lhs),
+            };
+
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Option<Expression> {
+        let mut lhs = self.unary()?;
+
+        loop {
+            let op = match () {
+                _ if self.eat('*') => '*',
+                _ if self.eat('/') => '/',
+                _ => return Some(lhs),
+            };
+
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := '-' unary | number | "path" | function '(' sum ')' | '(' sum ')'
+    fn unary(&mut self) -> Option<Expression> {
+        if self.eat('-') {
+            return Some(Expression::Negate(Box::new(self.unary()?)));
+        }
+
+        if self.eat('(') {
+            let expression = self.sum()?;
+            return self.eat(')').then_some(expression);
+        }
+
+        if self.eat('"') {
+            return self.counter();
+        }
+
+        match *self.chars.peek()? {
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+
+                Some(Expression::Number(number.parse().ok()?))
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    name.push(c);
+                }
+
+                let function = match name.as_str() {
+                    "sum" => Function::Sum,
+                    "avg" => Function::Avg,
+                    "min" => Function::Min,
+                    "max" => Function::Max,
+                    _ => return None,
+                };
+
+                if !self.eat('(') {
+                    return None;
+                }
+                let argument = self.sum()?;
+
+                self.eat(')')
+                    .then(|| Expression::Call(function, Box::new(argument)))
+            }
+            _ => None,
+        }
+    }
+
+    // backslashes are part of the path, only \" is an escape
+    fn counter(&mut self) -> Option<Expression> {
+        let mut path = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => break,
+                '\\' if self.chars.next_if_eq(&'"').is_some() => path.push('"'),
+                c => path.push(c),
+            }
+        }
+
+        let path = CounterPath::parse(&path)?;
+        let counter = self
+            .sources
+            .iter_mut()
+            .enumerate()
+            .find_map(|(source, counter_source)| {
+                counter_source
+                    .add_counter(&path)
+                    .map(|hcounter| (source, hcounter))
+            });
+
+        if counter.is_none() {
+            eprintln!("No source for {}", path);
+        }
+
+        Some(Expression::Counter(counter))
+    }
+}
+
+// a single value applies to every instance of the other side, otherwise the instances are
+// matched by name
+fn combine(op: char, lhs: Instances, rhs: Instances) -> Instances {
+    let apply = |x: f64, y: f64| match op {
+        '+' => x + y,
+        '-' => x - y,
+        '*' => x * y,
+        _ => x / y,
+    };
+
+    match (lhs.as_slice(), rhs.as_slice()) {
+        ([(lhs_name, x)], [(rhs_name, y)]) => {
+            let name = if lhs_name == rhs_name {
+                lhs_name.clone()
+            } else {
+                String::new()
+            };
+            vec![(name, apply(*x, *y))]
+        }
+        ([(_, x)], _) => rhs
+            .iter()
+            .map(|(name, y)| (name.clone(), apply(*x, *y)))
+            .collect(),
+        (_, [(_, y)]) => lhs
+            .iter()
+            .map(|(name, x)| (name.clone(), apply(*x, *y)))
+            .collect(),
+        _ => lhs
+            .iter()
+            .filter_map(|(name, x)| {
+                let (_, y) = rhs.iter().find(|(rhs_name, _)| rhs_name == name)?;
+                Some((name.clone(), apply(*x, *y)))
+            })
+            .collect(),
+    }
+}
+
+impl Expression {
+    fn parse(expression: &str, sources: &mut [Box<dyn CounterSource>]) -> Option<Self> {
+        let mut parser = Parser {
+            chars: expression.chars().peekable(),
+            sources,
+        };
+
+        let expression = parser.sum()?;
+        while parser.chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        parser.chars.peek().is_none().then_some(expression)
+    }
+
+    fn sources(&self, sources: &mut Vec<usize>) {
+        match self {
+            Self::Number(_) | Self::Counter(None) => (),
+            Self::Counter(Some((source, _))) => sources.push(*source),
+            Self::Negate(expression) | Self::Call(_, expression) => expression.sources(sources),
+            Self::Binary(_, lhs, rhs) => {
+                lhs.sources(sources);
+                rhs.sources(sources);
+            }
+        }
+    }
+
+    // None as soon as one of the counters has no data
+    fn evaluate(
+        &self,
+        sources: &[Box<dyn CounterSource>],
+        collected: &[bool],
+    ) -> Option<Instances> {
+        match self {
+            Self::Number(number) => Some(vec![(String::new(), *number)]),
+            Self::Counter(counter) => {
+                let (source, hcounter) = (*counter)?;
+
+                collected[source]
+                    .then(|| sources[source].get_values(hcounter))
+                    .flatten()
+                    .filter(|values| !values.is_empty())
+            }
+            Self::Negate(expression) => Some(
+                expression
+                    .evaluate(sources, collected)?
+                    .into_iter()
+                    .map(|(name, value)| (name, -value))
+                    .collect(),
+            ),
+            Self::Binary(op, lhs, rhs) => Some(combine(
+                *op,
+                lhs.evaluate(sources, collected)?,
+                rhs.evaluate(sources, collected)?,
+            )),
+            Self::Call(function, argument) => {
+                let instances = argument.evaluate(sources, collected)?;
+                let values = instances.iter().map(|(_, value)| *value);
+
+                let value = match function {
+                    Function::Sum => values.sum(),
+                    Function::Avg => values.sum::<f64>() / instances.len() as f64,
+                    Function::Min => values.fold(f64::MAX, f64::min),
+                    Function::Max => values.fold(f64::MIN, f64::max),
+                };
+
+                Some(vec![(String::new(), value)])
+            }
+        }
+    }
+}
+
+pub struct Derived {
+    expressions: Vec<(String, Expression)>,
+    curr: Vec<Option<Instances>>,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+}
+
+impl Derived {
+    // the counters of the expressions are added to `sources`, this source must not be among them
+    pub fn new(definitions: Vec<Definition>, sources: &mut [Box<dyn CounterSource>]) -> Self {
+        let expressions = definitions
+            .into_iter()
+            .filter_map(
+                |definition| match Expression::parse(&definition.expression, sources) {
+                    Some(expression) => Some((definition.name, expression)),
+                    None => {
+                        eprintln!("Invalid expression for {}", definition.name);
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        Self {
+            curr: expressions.iter().map(|_| None).collect(),
+            expressions,
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        }
+    }
+}
+
+impl CounterSource for Derived {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Derived" {
+            return None;
+        }
+
+        let expression = self
+            .expressions
+            .iter()
+            .position(|(name, _)| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), expression));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn offers(&self) -> Vec<CounterPath> {
+        super::paths_of(
+            "Derived",
+            self.expressions.iter().map(|(name, _)| name.as_str()),
+        )
+    }
+
+    // the values are computed in derive, once the other sources collected
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        true
+    }
+
+    fn reads(&self) -> Vec<usize> {
+        let mut sources = Vec::new();
+        for (_, expression) in self.expressions.iter() {
+            expression.sources(&mut sources);
+        }
+
+        sources
+    }
+
+    fn derive(&mut self, sources: &[Box<dyn CounterSource>], collected: &[bool]) {
+        for ((_, expression), curr) in self.expressions.iter().zip(self.curr.iter_mut()) {
+            *curr = expression.evaluate(sources, collected).map(|values| {
+                values
+                    .into_iter()
+                    .filter(|(_, value)| value.is_finite())
+                    .collect()
+            });
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, expression) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr[*expression].clone()?.into_iter(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // each counter has its own values, whatever the instance asked for
+    struct Fake;
+
+    const FAKE_COUNTERS: [&str; 4] = ["A", "B", "One", "Zero"];
+
+    impl CounterSource for Fake {
+        fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+            if path.object != "Fake" {
+                return None;
+            }
+
+            FAKE_COUNTERS.iter().position(|name| *name == path.counter)
+        }
+
+        fn remove_counter(&mut self, _hcounter: usize) {}
+
+        fn collect(&mut self, _elapsed: time::Duration) -> bool {
+            true
+        }
+
+        fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+            let values: &[(&str, f64)] = match hcounter {
+                0 => &[("x", 1.0), ("y", 2.0), ("z", 3.0)],
+                1 => &[("x", 10.0), ("y", 20.0)],
+                2 => &[("", 4.0)],
+                _ => &[("", 0.0)],
+            };
+
+            Some(
+                values
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), *value))
+                    .collect(),
+            )
+        }
+    }
+
+    fn derive(expression: &str) -> Option<Instances> {
+        let mut sources: Vec<Box<dyn CounterSource>> = vec![Box::new(Fake)];
+        let mut derived = Derived::new(
+            vec![Definition {
+                name: "Test".to_string(),
+                expression: expression.to_string(),
+            }],
+            &mut sources,
+        );
+        assert_eq!(derived.expressions.len(), 1, "{} is invalid", expression);
+
+        derived.derive(&sources, &[true]);
+        derived.curr[0].clone()
+    }
+
+    fn instances(values: &[(&str, f64)]) -> Option<Instances> {
+        Some(
+            values
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        assert_eq!(derive("1 + 2 * 3"), instances(&[("", 7.0)]));
+        assert_eq!(derive("(1 + 2) * 3"), instances(&[("", 9.0)]));
+        assert_eq!(derive("8 - 2 - 1"), instances(&[("", 5.0)]));
+        assert_eq!(derive("8 / 4 / 2"), instances(&[("", 1.0)]));
+        assert_eq!(derive("-2 * -3"), instances(&[("", 6.0)]));
+        assert_eq!(derive("1 - -(2 + 0.5)"), instances(&[("", 3.5)]));
+    }
+
+    #[test]
+    fn functions_reduce_the_instances() {
+        assert_eq!(derive(r#"sum("\Fake(*)\A")"#), instances(&[("", 6.0)]));
+        assert_eq!(derive(r#"avg("\Fake(*)\A")"#), instances(&[("", 2.0)]));
+        assert_eq!(derive(r#"min("\Fake(*)\A")"#), instances(&[("", 1.0)]));
+        assert_eq!(derive(r#"max("\Fake(*)\A") * 2"#), instances(&[("", 6.0)]));
+    }
+
+    #[test]
+    fn instances_are_combined() {
+        // a single value applies to every instance
+        assert_eq!(
+            derive(r#""\Fake(*)\A" * 2"#),
+            instances(&[("x", 2.0), ("y", 4.0), ("z", 6.0)])
+        );
+        assert_eq!(
+            derive(r#""\Fake\One" + "\Fake(*)\B""#),
+            instances(&[("x", 14.0), ("y", 24.0)])
+        );
+        // otherwise matched by name, z has no match
+        assert_eq!(
+            derive(r#""\Fake(*)\B" - "\Fake(*)\A""#),
+            instances(&[("x", 9.0), ("y", 18.0)])
+        );
+    }
+
+    #[test]
+    fn unknown_counters_have_no_data() {
+        assert_eq!(derive(r#""\Fake\Unknown" + 1"#), None);
+        assert_eq!(derive(r#""\Other\A" + 1"#), None);
+    }
+
+    #[test]
+    fn incomplete_expressions_are_rejected() {
+        let mut sources: Vec<Box<dyn CounterSource>> = vec![Box::new(Fake)];
+
+        for expression in ["1 +", "(1", "1 2", "sqrt(1)", r#"sum("\Fake(*)\A""#] {
+            assert!(Expression::parse(expression, &mut sources).is_none());
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_filtered_out() {
+        assert_eq!(derive(r#""\Fake(*)\A" / "\Fake\Zero""#), instances(&[]));
+        assert_eq!(
+            derive(r#""\Fake(*)\B" / ("\Fake(*)\A" - 1)"#),
+            instances(&[("y", 20.0)])
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+const SECTOR_SIZE: f64 = 512.0;
+
+const COUNTERS: [&str; 7] = [
+    "Disk Reads/sec",
+    "Disk Writes/sec",
+    "Disk Read Bytes/sec",
+    "Disk Write Bytes/sec",
+    "Disk Bytes/sec",
+    "Avg. Disk Queue Length",
+    "% Disk Time",
+];
+
+// reads, reads merged, sectors read, ms reading, writes, writes merged, sectors written,
+// ms writing, ios in progress, ms doing io, weighted ms doing io
+type Stats = [u64; 11];
+
+pub struct PhysicalDisk {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: Vec<(String, Stats)>,
+    curr: Vec<(String, Stats)>,
+    elapsed: f64,
+}
+
+impl PhysicalDisk {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: Vec::new(),
+            curr: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, Stats)>> {
+        let diskstats = fs::read_to_string(self.root.join("diskstats")).ok()?;
+
+        Some(
+            diskstats
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.split_whitespace().skip(2);
+                    let name = fields.next()?;
+
+                    let mut stats = Stats::default();
+                    for (stat, field) in stats.iter_mut().zip(fields) {
+                        *stat = field.parse().ok()?;
+                    }
+
+                    Some((name.to_string(), stats))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl CounterSource for PhysicalDisk {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "PhysicalDisk" {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr);
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        if self.prev.is_empty() || self.elapsed <= 0.0 {
+            return None;
+        }
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let (_, prev) = self.prev.iter().find(|(prev_name, _)| prev_name == name)?;
+                let delta = |index: usize| curr[index].saturating_sub(prev[index]) as f64;
+
+                let value = match counter {
+                    0 => delta(0) / self.elapsed,
+                    1 => delta(4) / self.elapsed,
+                    2 => delta(2) * SECTOR_SIZE / self.elapsed,
+                    3 => delta(6) * SECTOR_SIZE / self.elapsed,
+                    4 => (delta(2) + delta(6)) * SECTOR_SIZE / self.elapsed,
+                    5 => delta(10) / (self.elapsed * 1000.0),
+                    _ => f64::min(100.0, delta(9) / (self.elapsed * 10.0)),
+                };
+
+                Some((name.clone(), value))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn rates_come_from_the_deltas() {
+        let root = env::temp_dir().join(format!("pdhv-diskstats-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let write_diskstats =
+            |diskstats: &str| fs::write(root.join("diskstats"), diskstats).unwrap();
+
+        let mut disk = PhysicalDisk::new(&root);
+        let mut add = |counter: &str| {
+            disk.add_counter(&CounterPath::parse(counter).unwrap())
+                .unwrap()
+        };
+        let (reads, writes, read_bytes, write_bytes, bytes, queue, time) = (
+            add(r"\PhysicalDisk(sda)\Disk Reads/sec"),
+            add(r"\PhysicalDisk(sda)\Disk Writes/sec"),
+            add(r"\PhysicalDisk(sda)\Disk Read Bytes/sec"),
+            add(r"\PhysicalDisk(sda)\Disk Write Bytes/sec"),
+            add(r"\PhysicalDisk(sda)\Disk Bytes/sec"),
+            add(r"\PhysicalDisk(sda)\Avg. Disk Queue Length"),
+            add(r"\PhysicalDisk(*)\% Disk Time"),
+        );
+        assert_eq!(
+            disk.add_counter(&CounterPath::parse(r"\PhysicalDisk(*)\Split IO/Sec").unwrap()),
+            None
+        );
+
+        write_diskstats(
+            "   8       0 sda 100 0 2000 50 200 0 4000 80 0 500 1000 0 0 0 0\n \
+             259       0 nvme0n1 10 0 80 5 10 0 80 5 0 100 100\n",
+        );
+        assert!(disk.collect(time::Duration::from_secs(1)));
+        assert_eq!(disk.get_values(reads), None);
+
+        // nvme0n1 was busy for longer than the interval, with requests in parallel
+        write_diskstats(
+            "   8       0 sda 120 0 2400 60 240 0 4800 90 1 1500 4000 0 0 0 0\n \
+             259       0 nvme0n1 10 0 80 5 10 0 80 5 0 2600 2600\n",
+        );
+        assert!(disk.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        let value = |hcounter: usize| disk.get_values(hcounter).map(|values| values[0].1);
+        assert_eq!(value(reads), Some(10.0));
+        assert_eq!(value(writes), Some(20.0));
+        assert_eq!(value(read_bytes), Some(400.0 * 512.0 / 2.0));
+        assert_eq!(value(write_bytes), Some(800.0 * 512.0 / 2.0));
+        assert_eq!(value(bytes), Some(1200.0 * 512.0 / 2.0));
+        assert_eq!(value(queue), Some(1.5));
+        assert_eq!(
+            disk.get_values(time),
+            Some(vec![
+                ("sda".to_string(), 50.0),
+                ("nvme0n1".to_string(), 100.0)
+            ])
+        );
+    }
+}
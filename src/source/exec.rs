@@ -0,0 +1,329 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use std::{
+    collections,
+    io::Read,
+    process::{self, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    thread, time,
+};
+
+use super::{CounterPath, CounterSource};
+
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Parse {
+    // the first number of the output
+    #[default]
+    Number,
+    // "name value" lines, one instance per line
+    Lines,
+    // a `value` group, or the first group, and an optional `name` group per match
+    Regex(String),
+}
+
+#[derive(Deserialize)]
+pub struct Definition {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub parse: Parse,
+    #[serde(default = "default_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout() -> u64 {
+    500
+}
+
+struct Command {
+    definition: Definition,
+    regex: Option<Regex>,
+}
+
+impl Command {
+    fn spawn(&self) -> Option<(process::Child, thread::JoinHandle<String>)> {
+        #[cfg(windows)]
+        let mut command = process::Command::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+
+        #[cfg(not(windows))]
+        let mut command = process::Command::new("sh");
+        #[cfg(not(windows))]
+        command.arg("-c");
+
+        let mut child = command
+            .arg(&self.definition.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        // read while the command runs, a full pipe would block it
+        let mut stdout = child.stdout.take()?;
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            output
+        });
+
+        Some((child, reader))
+    }
+
+    fn parse(&self, output: &str) -> Option<Vec<(String, f64)>> {
+        let values = match (&self.definition.parse, &self.regex) {
+            (Parse::Number, _) => vec![(
+                String::new(),
+                output.split_whitespace().next()?.parse().ok()?,
+            )],
+            (Parse::Lines, _) => output
+                .lines()
+                .filter_map(|line| {
+                    let (name, value) = line.trim().rsplit_once(char::is_whitespace)?;
+                    Some((name.trim().to_string(), value.parse().ok()?))
+                })
+                .collect(),
+            (Parse::Regex(_), Some(regex)) => regex
+                .captures_iter(output)
+                .filter_map(|captures| {
+                    let value = captures.name("value").or_else(|| captures.get(1))?;
+                    let name = captures.name("name").map_or("", |name| name.as_str());
+
+                    Some((name.to_string(), value.as_str().trim().parse().ok()?))
+                })
+                .collect(),
+            (Parse::Regex(_), None) => return None,
+        };
+
+        Some(values)
+    }
+}
+
+type Outputs = Vec<Option<Vec<(String, f64)>>>;
+
+// run the used commands side by side, a command still running at its timeout has no data
+fn run(commands: &[Command], used: &[usize]) -> Outputs {
+    let start = time::Instant::now();
+
+    let mut running = used
+        .iter()
+        .map(|index| (*index, commands[*index].spawn()))
+        .collect::<Vec<_>>();
+    let mut outputs = commands.iter().map(|_| None).collect::<Vec<_>>();
+
+    while running.iter().any(|(_, child)| child.is_some()) {
+        for (index, child) in running.iter_mut() {
+            let timeout = time::Duration::from_millis(commands[*index].definition.timeout_ms);
+
+            let status = match child {
+                Some((process, _)) => match process.try_wait() {
+                    Ok(Some(status)) => Some(status.success()),
+                    Ok(None) if start.elapsed() < timeout => continue,
+                    _ => {
+                        let _ = process.kill();
+                        let _ = process.wait();
+                        None
+                    }
+                },
+                None => continue,
+            };
+
+            // a killed shell can leave children holding stdout, don't wait on the reader
+            let (_, reader) = child.take().unwrap();
+            if status == Some(true) {
+                let output = reader.join().unwrap_or_default();
+                outputs[*index] = commands[*index].parse(&output);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    outputs
+}
+
+pub struct Exec {
+    names: Vec<String>,
+    // the used commands to run, the thread is gone with the source
+    tx: mpsc::Sender<Vec<usize>>,
+    ran: Arc<Mutex<Outputs>>,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    curr: Outputs,
+}
+
+impl Exec {
+    pub fn new(definitions: Vec<Definition>) -> Self {
+        let commands = definitions
+            .into_iter()
+            .map(|definition| Command {
+                regex: match &definition.parse {
+                    Parse::Regex(regex) => Regex::new(regex)
+                        .map_err(|err| {
+                            eprintln!("Invalid regex for {} err({})", definition.name, err)
+                        })
+                        .ok(),
+                    _ => None,
+                },
+                definition,
+            })
+            .collect::<Vec<_>>();
+
+        let names = commands
+            .iter()
+            .map(|command| command.definition.name.clone())
+            .collect::<Vec<_>>();
+        let ran = Arc::new(Mutex::new(
+            commands.iter().map(|_| None).collect::<Vec<_>>(),
+        ));
+        let thread_ran = ran.clone();
+
+        let (tx, rx) = mpsc::channel::<Vec<usize>>();
+        thread::spawn(move || {
+            while let Ok(mut used) = rx.recv() {
+                // the collections that came during the last run are run once
+                while let Ok(newer) = rx.try_recv() {
+                    used = newer;
+                }
+
+                let outputs = run(&commands, &used);
+                *thread_ran.lock().unwrap() = outputs;
+            }
+        });
+
+        Self {
+            curr: names.iter().map(|_| None).collect(),
+            names,
+            tx,
+            ran,
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        }
+    }
+}
+
+impl CounterSource for Exec {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Exec" {
+            return None;
+        }
+
+        let command = self.names.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), command));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn offers(&self) -> Vec<CounterPath> {
+        super::paths_of("Exec", self.names.iter().map(String::as_str))
+    }
+
+    // the commands run on their own thread, the values are the ones of its last run
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        let used = (0..self.names.len())
+            .filter(|index| self.counters.values().any(|(_, used)| used == index))
+            .collect::<Vec<_>>();
+
+        let _ = self.tx.send(used);
+        self.curr = self.ran.lock().unwrap().clone();
+
+        true
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, command) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr[*command].clone()?.into_iter(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec(command: &str, timeout_ms: u64) -> (Exec, usize) {
+        let mut exec = Exec::new(vec![Definition {
+            name: "Test".to_string(),
+            command: command.to_string(),
+            parse: Parse::Number,
+            timeout_ms,
+        }]);
+        let hcounter = exec
+            .add_counter(&CounterPath::parse(r"\Exec\Test").unwrap())
+            .unwrap();
+
+        (exec, hcounter)
+    }
+
+    // polls the collection until the command ran
+    fn wait_for(exec: &mut Exec, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        (0..100).find_map(|_| {
+            exec.collect(time::Duration::ZERO);
+            thread::sleep(time::Duration::from_millis(20));
+
+            exec.get_values(hcounter)
+        })
+    }
+
+    #[test]
+    fn collect_reads_the_last_run() {
+        let (mut exec, hcounter) = exec("echo 42", 500);
+
+        assert_eq!(
+            wait_for(&mut exec, hcounter),
+            Some(vec![(String::new(), 42.0)])
+        );
+    }
+
+    #[test]
+    fn offers_every_command() {
+        let (exec, _) = exec("echo 42", 500);
+
+        assert_eq!(
+            exec.offers(),
+            vec![CounterPath::parse(r"\Exec\Test").unwrap()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_does_not_wait_on_commands() {
+        let (mut exec, hcounter) = exec("sleep 0.3; echo 1", 1000);
+
+        let start = time::Instant::now();
+        assert!(exec.collect(time::Duration::ZERO));
+        assert!(start.elapsed() < time::Duration::from_millis(100));
+        assert_eq!(exec.get_values(hcounter), None);
+
+        assert_eq!(
+            wait_for(&mut exec, hcounter),
+            Some(vec![(String::new(), 1.0)])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_are_killed_at_their_timeout() {
+        let (mut exec, hcounter) = exec("sleep 5; echo 1", 100);
+
+        exec.collect(time::Duration::ZERO);
+        thread::sleep(time::Duration::from_millis(400));
+        exec.collect(time::Duration::ZERO);
+        assert_eq!(exec.get_values(hcounter), None);
+    }
+}
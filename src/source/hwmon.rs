@@ -0,0 +1,221 @@
+use std::{collections, fs, path, time};
+
+use super::{CounterPath, CounterSource};
+
+// (counter, sysfs file prefix, divisor to the displayed unit)
+const COUNTERS: [(&str, &str, f64); 3] = [
+    ("Temperature", "temp", 1000.0),
+    ("Fan Speed", "fan", 1.0),
+    ("Voltage", "in", 1000.0),
+];
+
+pub struct Sensor {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    curr: Vec<(String, usize, f64)>,
+}
+
+impl Sensor {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            curr: Vec::new(),
+        }
+    }
+
+    fn read_chip(dir: &path::Path, sensors: &mut Vec<(String, usize, f64)>) {
+        let read = |file: &str| {
+            fs::read_to_string(dir.join(file))
+                .ok()
+                .map(|value| value.trim().to_string())
+        };
+
+        let chip =
+            read("name").unwrap_or_else(|| dir.file_name().unwrap().to_string_lossy().into());
+
+        let mut inputs = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter_map(|file| Some(file.strip_suffix("_input")?.to_string()))
+                .collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        inputs.sort_unstable();
+
+        for input in inputs {
+            let kind = match COUNTERS.iter().position(|(_, prefix, _)| {
+                input
+                    .strip_prefix(prefix)
+                    .is_some_and(|index| index.parse::<u32>().is_ok())
+            }) {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            let value = match read(&format!("{}_input", input))
+                .and_then(|value| value.parse::<f64>().ok())
+            {
+                Some(value) => value / COUNTERS[kind].2,
+                None => continue,
+            };
+
+            let label = read(&format!("{}_label", input)).unwrap_or(input);
+            sensors.push((format!("{}/{}", chip, label), kind, value));
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, usize, f64)>> {
+        let mut chips = fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect::<Vec<_>>();
+        chips.sort_unstable();
+
+        let mut sensors = Vec::new();
+        for chip in chips {
+            Self::read_chip(&chip, &mut sensors);
+        }
+
+        // same chip and label twice, number them like pdh does
+        let mut seen = collections::HashMap::<String, usize>::new();
+        for (name, _, _) in sensors.iter_mut() {
+            let count = seen.entry(name.clone()).or_insert(0);
+            if *count > 0 {
+                *name = format!("{}#{}", name, count);
+            }
+            *count += 1;
+        }
+
+        Some(sensors)
+    }
+}
+
+impl CounterSource for Sensor {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Sensor" {
+            return None;
+        }
+
+        let counter = COUNTERS
+            .iter()
+            .position(|(name, _, _)| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        if self.counters.is_empty() {
+            return true;
+        }
+
+        match self.read() {
+            Some(curr) => {
+                self.curr = curr;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr
+                .iter()
+                .filter(|(_, kind, _)| kind == counter)
+                .map(|(name, _, value)| (name.clone(), *value)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    fn write_chip(root: &path::Path, chip: &str, files: &[(&str, &str)]) {
+        let dir = root.join(chip);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, value) in files {
+            fs::write(dir.join(file), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    fn values(sensor: &mut Sensor, path: &str) -> Option<Vec<(String, f64)>> {
+        let hcounter = sensor.add_counter(&CounterPath::parse(path).unwrap())?;
+        sensor.collect(time::Duration::from_secs(1));
+
+        sensor.get_values(hcounter)
+    }
+
+    #[test]
+    fn names_sensors_by_chip_and_label() {
+        let root = env::temp_dir().join(format!("pdhv-hwmon-{}", process::id()));
+        write_chip(
+            &root,
+            "hwmon0",
+            &[
+                ("name", "coretemp"),
+                ("temp1_input", "45000"),
+                ("temp1_label", "Core 0"),
+                ("temp1_crit", "100000"),
+                ("temp2_input", "47500"),
+                ("temp2_label", "Core 1"),
+            ],
+        );
+        // a second socket, same chip name and labels
+        write_chip(
+            &root,
+            "hwmon1",
+            &[
+                ("name", "coretemp"),
+                ("temp1_input", "50000"),
+                ("temp1_label", "Core 0"),
+            ],
+        );
+        // no name and no labels
+        write_chip(
+            &root,
+            "hwmon2",
+            &[("fan1_input", "1200"), ("in0_input", "1100")],
+        );
+
+        let mut sensor = Sensor::new(&root);
+        assert_eq!(
+            values(&mut sensor, r"\Sensor(*)\Temperature"),
+            Some(vec![
+                ("coretemp/Core 0".to_string(), 45.0),
+                ("coretemp/Core 1".to_string(), 47.5),
+                ("coretemp/Core 0#1".to_string(), 50.0),
+            ])
+        );
+        assert_eq!(
+            values(&mut sensor, r"\Sensor(coretemp/Core 0#1)\Temperature"),
+            Some(vec![("coretemp/Core 0#1".to_string(), 50.0)])
+        );
+        assert_eq!(
+            values(&mut sensor, r"\Sensor(*)\Fan Speed"),
+            Some(vec![("hwmon2/fan1".to_string(), 1200.0)])
+        );
+        assert_eq!(
+            values(&mut sensor, r"\Sensor(*)\Voltage"),
+            Some(vec![("hwmon2/in0".to_string(), 1.1)])
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
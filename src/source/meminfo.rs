@@ -0,0 +1,209 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+// vmstat pgpgin/pgpgout are in KiB, assume 4 KiB pages
+const PAGE_KIB: f64 = 4.0;
+
+const COUNTERS: [&str; 8] = [
+    "Available Bytes",
+    "Committed Bytes",
+    "Commit Limit",
+    "Cache Bytes",
+    "Swap Bytes",
+    "Page Faults/sec",
+    "Pages Input/sec",
+    "Pages Output/sec",
+];
+
+pub struct Memory {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, usize>,
+    last_id: usize,
+    meminfo: collections::HashMap<String, u64>,
+    prev_vmstat: collections::HashMap<String, u64>,
+    curr_vmstat: collections::HashMap<String, u64>,
+    elapsed: f64,
+}
+
+impl Memory {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            meminfo: collections::HashMap::new(),
+            prev_vmstat: collections::HashMap::new(),
+            curr_vmstat: collections::HashMap::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read(&self, file: &str) -> Option<collections::HashMap<String, u64>> {
+        let content = fs::read_to_string(self.root.join(file)).ok()?;
+
+        Some(
+            content
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.split_whitespace();
+                    let name = fields.next()?.trim_end_matches(':');
+                    let value = fields.next()?.parse::<u64>().ok()?;
+
+                    Some((name.to_string(), value))
+                })
+                .collect(),
+        )
+    }
+
+    fn bytes(&self, fields: &[&str]) -> Option<f64> {
+        fields.iter().try_fold(0.0, |acc, field| {
+            Some(acc + *self.meminfo.get(*field)? as f64 * 1024.0)
+        })
+    }
+
+    fn rate(&self, field: &str) -> Option<f64> {
+        if self.elapsed <= 0.0 {
+            return None;
+        }
+
+        let delta = self
+            .curr_vmstat
+            .get(field)?
+            .saturating_sub(*self.prev_vmstat.get(field)?);
+
+        Some(delta as f64 / self.elapsed)
+    }
+}
+
+impl CounterSource for Memory {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Memory" || path.instance.is_some() {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters.insert(self.last_id, counter);
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        match (self.read("meminfo"), self.read("vmstat")) {
+            (Some(meminfo), Some(vmstat)) => {
+                self.meminfo = meminfo;
+                self.prev_vmstat = mem::replace(&mut self.curr_vmstat, vmstat);
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let value = match self.counters.get(&hcounter)? {
+            0 => self.bytes(&["MemAvailable"])?,
+            1 => self.bytes(&["Committed_AS"])?,
+            2 => self.bytes(&["CommitLimit"])?,
+            3 => self.bytes(&["Cached", "Buffers"])?,
+            4 => self.bytes(&["SwapTotal"])? - self.bytes(&["SwapFree"])?,
+            5 => self.rate("pgfault")?,
+            6 => self.rate("pgpgin")? / PAGE_KIB,
+            _ => self.rate("pgpgout")? / PAGE_KIB,
+        };
+
+        Some(vec![(String::new(), value)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn reads_meminfo_and_vmstat() {
+        let root = env::temp_dir().join(format!("pdhv-meminfo-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let write = |meminfo_kib: u64, pgfault: u64, pgpgin: u64, pgpgout: u64| {
+            fs::write(
+                root.join("meminfo"),
+                format!(
+                    "MemTotal:       16000000 kB\n\
+                     MemAvailable:   {} kB\n\
+                     Buffers:            1000 kB\n\
+                     Cached:             3000 kB\n\
+                     SwapTotal:          8000 kB\n\
+                     SwapFree:           6000 kB\n\
+                     CommitLimit:       16000 kB\n\
+                     Committed_AS:      12000 kB\n\
+                     HugePages_Total:      0\n",
+                    meminfo_kib
+                ),
+            )
+            .unwrap();
+            fs::write(
+                root.join("vmstat"),
+                format!(
+                    "nr_free_pages 100\npgpgin {}\npgpgout {}\npgfault {}\n",
+                    pgpgin, pgpgout, pgfault
+                ),
+            )
+            .unwrap();
+        };
+
+        let mut memory = Memory::new(&root);
+        let values = |memory: &mut Memory, counter: &str| {
+            let path = CounterPath::parse(&format!(r"\Memory\{}", counter)).unwrap();
+            let hcounter = memory.add_counter(&path)?;
+            let values = memory.get_values(hcounter);
+            memory.remove_counter(hcounter);
+
+            values.map(|values| values[0].1)
+        };
+
+        assert_eq!(
+            memory.add_counter(&CounterPath::parse(r"\Memory(0)\Available Bytes").unwrap()),
+            None
+        );
+        assert_eq!(values(&mut memory, "Pool Paged Bytes"), None);
+
+        write(2048, 1000, 400, 800);
+        assert!(memory.collect(time::Duration::ZERO));
+        assert_eq!(
+            values(&mut memory, "Available Bytes"),
+            Some(2048.0 * 1024.0)
+        );
+        assert_eq!(values(&mut memory, "Page Faults/sec"), None);
+
+        write(1024, 3000, 480, 1600);
+        assert!(memory.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        // in bytes from KiB
+        assert_eq!(
+            values(&mut memory, "Available Bytes"),
+            Some(1024.0 * 1024.0)
+        );
+        assert_eq!(
+            values(&mut memory, "Committed Bytes"),
+            Some(12000.0 * 1024.0)
+        );
+        assert_eq!(values(&mut memory, "Commit Limit"), Some(16000.0 * 1024.0));
+        assert_eq!(values(&mut memory, "Cache Bytes"), Some(4000.0 * 1024.0));
+        assert_eq!(values(&mut memory, "Swap Bytes"), Some(2000.0 * 1024.0));
+        // per second over two seconds, in pages of 4 KiB
+        assert_eq!(values(&mut memory, "Page Faults/sec"), Some(1000.0));
+        assert_eq!(values(&mut memory, "Pages Input/sec"), Some(10.0));
+        assert_eq!(values(&mut memory, "Pages Output/sec"), Some(100.0));
+
+        assert!(!memory.collect(time::Duration::from_secs(1)));
+    }
+}
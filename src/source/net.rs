@@ -0,0 +1,333 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+// (counter, receive field, transmit field) of a /proc/net/dev line
+const INTERFACE_COUNTERS: [(&str, Option<usize>, Option<usize>); 10] = [
+    ("Bytes Total/sec", Some(0), Some(8)),
+    ("Bytes Received/sec", Some(0), None),
+    ("Bytes Sent/sec", None, Some(8)),
+    ("Packets/sec", Some(1), Some(9)),
+    ("Packets Received/sec", Some(1), None),
+    ("Packets Sent/sec", None, Some(9)),
+    ("Packets Received Errors/sec", Some(2), None),
+    ("Packets Outbound Errors/sec", None, Some(10)),
+    ("Packets Received Discarded/sec", Some(3), None),
+    ("Packets Outbound Discarded/sec", None, Some(11)),
+];
+
+// (counter, /proc/net/snmp Tcp field, is a rate)
+const TCP_COUNTERS: [(&str, &str, bool); 4] = [
+    ("Segments Retransmitted/sec", "RetransSegs", true),
+    ("Segments Received/sec", "InSegs", true),
+    ("Segments Sent/sec", "OutSegs", true),
+    ("Connections Established", "CurrEstab", false),
+];
+
+type Stats = [u64; 16];
+
+pub struct NetworkInterface {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: Vec<(String, Stats)>,
+    curr: Vec<(String, Stats)>,
+    elapsed: f64,
+}
+
+impl NetworkInterface {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: Vec::new(),
+            curr: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, Stats)>> {
+        let dev = fs::read_to_string(self.root.join("net").join("dev")).ok()?;
+
+        Some(
+            dev.lines()
+                .filter_map(|line| {
+                    let (name, fields) = line.split_once(':')?;
+
+                    let mut stats = Stats::default();
+                    for (stat, field) in stats.iter_mut().zip(fields.split_whitespace()) {
+                        *stat = field.parse().ok()?;
+                    }
+
+                    Some((name.trim().to_string(), stats))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl CounterSource for NetworkInterface {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Network Interface" {
+            return None;
+        }
+
+        let counter = INTERFACE_COUNTERS
+            .iter()
+            .position(|(name, _, _)| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr);
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        if self.prev.is_empty() || self.elapsed <= 0.0 {
+            return None;
+        }
+
+        let (_, receive, transmit) = INTERFACE_COUNTERS[*counter];
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let (_, prev) = self.prev.iter().find(|(prev_name, _)| prev_name == name)?;
+                let delta = |index: Option<usize>| {
+                    index.map_or(0, |index| curr[index].saturating_sub(prev[index])) as f64
+                };
+
+                Some((
+                    name.clone(),
+                    (delta(receive) + delta(transmit)) / self.elapsed,
+                ))
+            }),
+        ))
+    }
+}
+
+pub struct Tcpv4 {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, usize>,
+    last_id: usize,
+    prev: collections::HashMap<String, i64>,
+    curr: collections::HashMap<String, i64>,
+    elapsed: f64,
+}
+
+impl Tcpv4 {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: collections::HashMap::new(),
+            curr: collections::HashMap::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read(&self) -> Option<collections::HashMap<String, i64>> {
+        let snmp = fs::read_to_string(self.root.join("net").join("snmp")).ok()?;
+
+        // a header line followed by a value line for each protocol
+        let mut lines = snmp.lines().filter(|line| line.starts_with("Tcp:"));
+        let (names, values) = (lines.next()?, lines.next()?);
+
+        Some(
+            names
+                .split_whitespace()
+                .zip(values.split_whitespace())
+                .skip(1)
+                .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+                .collect(),
+        )
+    }
+}
+
+impl CounterSource for Tcpv4 {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "TCPv4" || path.instance.is_some() {
+            return None;
+        }
+
+        let counter = TCP_COUNTERS
+            .iter()
+            .position(|(name, _, _)| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters.insert(self.last_id, counter);
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr);
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (_, field, is_rate) = TCP_COUNTERS[*self.counters.get(&hcounter)?];
+        let curr = *self.curr.get(field)?;
+
+        let value = if is_rate {
+            if self.elapsed <= 0.0 {
+                return None;
+            }
+
+            curr.saturating_sub(*self.prev.get(field)?).max(0) as f64 / self.elapsed
+        } else {
+            curr as f64
+        };
+
+        Some(vec![(String::new(), value)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    fn value(source: &mut dyn CounterSource, path: &str) -> Option<Vec<(String, f64)>> {
+        let hcounter = source.add_counter(&CounterPath::parse(path).unwrap())?;
+        let values = source.get_values(hcounter);
+        source.remove_counter(hcounter);
+
+        values
+    }
+
+    #[test]
+    fn interface_rates_come_from_the_deltas() {
+        let root = env::temp_dir().join(format!("pdhv-net-dev-{}", process::id()));
+        fs::create_dir_all(root.join("net")).unwrap();
+        let write_dev = |eth0: &str| {
+            fs::write(
+                root.join("net").join("dev"),
+                format!(
+                    "Inter-|   Receive                            |  Transmit\n \
+                     face |bytes packets errs drop fifo frame compressed multicast|bytes packets errs drop fifo colls carrier compressed\n    \
+                     lo: 500 5 0 0 0 0 0 0 500 5 0 0 0 0 0 0\n  \
+                     eth0: {}\n",
+                    eth0
+                ),
+            )
+            .unwrap()
+        };
+
+        let mut net = NetworkInterface::new(&root);
+        write_dev("1000 10 0 0 0 0 0 0 2000 20 0 0 0 0 0 0");
+        assert!(net.collect(time::Duration::from_secs(1)));
+        assert_eq!(value(&mut net, r"\Network Interface(*)\Packets/sec"), None);
+
+        write_dev("5000 30 2 4 0 0 0 0 4000 50 6 8 0 0 0 0");
+        assert!(net.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            value(&mut net, r"\Network Interface(*)\Bytes Total/sec"),
+            Some(vec![("lo".to_string(), 0.0), ("eth0".to_string(), 3000.0)])
+        );
+        for (counter, rate) in [
+            ("Bytes Received/sec", 2000.0),
+            ("Bytes Sent/sec", 1000.0),
+            ("Packets/sec", 25.0),
+            ("Packets Received/sec", 10.0),
+            ("Packets Sent/sec", 15.0),
+            ("Packets Received Errors/sec", 1.0),
+            ("Packets Outbound Errors/sec", 3.0),
+            ("Packets Received Discarded/sec", 2.0),
+            ("Packets Outbound Discarded/sec", 4.0),
+        ] {
+            assert_eq!(
+                value(&mut net, &format!(r"\Network Interface(eth0)\{}", counter)),
+                Some(vec![("eth0".to_string(), rate)]),
+                "{}",
+                counter
+            );
+        }
+        assert_eq!(
+            value(&mut net, r"\Network Interface(*)\Output Queue Length"),
+            None
+        );
+    }
+
+    #[test]
+    fn tcp_counters_come_from_snmp() {
+        let root = env::temp_dir().join(format!("pdhv-net-snmp-{}", process::id()));
+        fs::create_dir_all(root.join("net")).unwrap();
+        let write_snmp = |tcp: &str| {
+            fs::write(
+                root.join("net").join("snmp"),
+                format!(
+                    "Ip: Forwarding DefaultTTL InReceives\n\
+                     Ip: 1 64 123456\n\
+                     Tcp: RtoAlgorithm RtoMin RtoMax MaxConn ActiveOpens PassiveOpens AttemptFails \
+                     EstabResets CurrEstab InSegs OutSegs RetransSegs InErrs OutRsts InCsumErrors\n\
+                     Tcp: {}\n\
+                     Udp: InDatagrams NoPorts\n\
+                     Udp: 10 0\n",
+                    tcp
+                ),
+            )
+            .unwrap()
+        };
+
+        let mut tcp = Tcpv4::new(&root);
+        write_snmp("1 200 120000 -1 10 5 0 0 4 1000 900 10 0 0 0");
+        assert!(tcp.collect(time::Duration::from_secs(1)));
+        assert_eq!(
+            value(&mut tcp, r"\TCPv4\Connections Established"),
+            Some(vec![(String::new(), 4.0)])
+        );
+        assert_eq!(value(&mut tcp, r"\TCPv4\Segments Sent/sec"), None);
+
+        write_snmp("1 200 120000 -1 12 5 0 0 6 1400 1500 16 0 0 0");
+        assert!(tcp.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        for (counter, expected) in [
+            ("Segments Retransmitted/sec", 3.0),
+            ("Segments Received/sec", 200.0),
+            ("Segments Sent/sec", 300.0),
+            ("Connections Established", 6.0),
+        ] {
+            assert_eq!(
+                value(&mut tcp, &format!(r"\TCPv4\{}", counter)),
+                Some(vec![(String::new(), expected)]),
+                "{}",
+                counter
+            );
+        }
+        assert_eq!(value(&mut tcp, r"\TCPv4(0)\Connections Established"), None);
+    }
+}
@@ -0,0 +1,143 @@
+use windows_sys::Win32::{
+    Foundation::{ERROR_SUCCESS, HWND},
+    System::Performance::{
+        PdhAddCounterW, PdhBrowseCountersW, PdhCloseQuery, PdhCollectQueryData,
+        PdhGetFormattedCounterArrayW, PdhOpenQueryW, PdhRemoveCounter, PDH_BROWSE_DLG_CONFIG_W,
+        PDH_FMT_COUNTERVALUE_ITEM_W, PDH_FMT_DOUBLE, PDH_MAX_COUNTER_PATH, PDH_MORE_DATA,
+    },
+};
+
+use std::{collections, iter, mem, ptr, time};
+
+use super::{CounterPath, CounterSource};
+
+pub struct Pdh {
+    hquery: isize,
+    hcounters: collections::HashMap<usize, isize>,
+    last_id: usize,
+}
+
+impl Pdh {
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open() -> Self {
+        let mut hquery = 0;
+        assert!(PdhOpenQueryW(ptr::null(), 0, &mut hquery) == ERROR_SUCCESS);
+
+        Self {
+            hquery,
+            hcounters: collections::HashMap::new(),
+            last_id: 0,
+        }
+    }
+}
+
+impl CounterSource for Pdh {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        let path = path.to_wide();
+
+        let mut hcounter = 0;
+        if unsafe { PdhAddCounterW(self.hquery, path.as_ptr(), 0, &mut hcounter) } != ERROR_SUCCESS
+        {
+            return None;
+        };
+
+        self.last_id += 1;
+        self.hcounters.insert(self.last_id, hcounter);
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        if let Some(hcounter) = self.hcounters.remove(&hcounter) {
+            unsafe { PdhRemoveCounter(hcounter) };
+        }
+    }
+
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        unsafe { PdhCollectQueryData(self.hquery) == ERROR_SUCCESS }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let hcounter = *self.hcounters.get(&hcounter)?;
+
+        unsafe {
+            let mut buffer_size = 0;
+            let mut item_count = 0;
+
+            if PdhGetFormattedCounterArrayW(
+                hcounter,
+                PDH_FMT_DOUBLE,
+                &mut buffer_size,
+                &mut item_count,
+                ptr::null_mut(),
+            ) != PDH_MORE_DATA
+            {
+                return None;
+            };
+
+            let mut item_buffer = Vec::from_iter(
+                iter::repeat(mem::zeroed::<PDH_FMT_COUNTERVALUE_ITEM_W>())
+                    .take(buffer_size as usize / mem::size_of::<PDH_FMT_COUNTERVALUE_ITEM_W>() + 1),
+            );
+
+            if PdhGetFormattedCounterArrayW(
+                hcounter,
+                PDH_FMT_DOUBLE,
+                &mut buffer_size,
+                &mut item_count,
+                item_buffer.as_mut_ptr(),
+            ) != ERROR_SUCCESS
+            {
+                return None;
+            };
+
+            Some(
+                item_buffer
+                    .iter()
+                    .take(item_count as usize)
+                    .map(|item| {
+                        let mut curr = item.szName;
+                        let name = String::from_utf16(
+                            iter::repeat_with(|| {
+                                let tmp = *curr;
+                                curr = curr.add(1);
+                                tmp
+                            })
+                            .take_while(|tmp| tmp != &0)
+                            .collect::<Vec<u16>>()
+                            .as_slice(),
+                        )
+                        .unwrap();
+
+                        (name, item.FmtValue.Anonymous.doubleValue)
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    fn close(&mut self) {
+        unsafe { assert!(PdhCloseQuery(self.hquery) == ERROR_SUCCESS) };
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn browse_counters(hwnd: HWND) -> Option<CounterPath> {
+    let mut path_buffer = Vec::from_iter(iter::repeat(0_u16).take(PDH_MAX_COUNTER_PATH as usize));
+
+    let mut bw_config: PDH_BROWSE_DLG_CONFIG_W = mem::zeroed();
+    bw_config._bitfield = 0b0000_0000_0000_0000_0000_0001_0001_0111;
+    bw_config.hWndOwner = hwnd;
+    bw_config.szReturnPathBuffer = path_buffer.as_mut_ptr();
+    bw_config.cchReturnPathLength = PDH_MAX_COUNTER_PATH;
+    bw_config.CallBackStatus = ERROR_SUCCESS as _;
+    bw_config.szDialogBoxCaption = &mut 0;
+
+    if PdhBrowseCountersW(&bw_config) != ERROR_SUCCESS || path_buffer[0] == 0 {
+        return None;
+    };
+
+    path_buffer.retain(|&c| c != 0);
+
+    CounterPath::from_wide(&path_buffer)
+}
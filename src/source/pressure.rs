@@ -0,0 +1,240 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+const RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+// (counter, "some" or "full" line, stall field)
+const COUNTERS: [(&str, &str, usize); 6] = [
+    ("% Some avg10", "some", 0),
+    ("% Some avg60", "some", 1),
+    ("Some Stall Time/sec", "some", 2),
+    ("% Full avg10", "full", 0),
+    ("% Full avg60", "full", 1),
+    ("Full Stall Time/sec", "full", 2),
+];
+
+#[derive(Clone, Copy, Default)]
+pub struct Stall {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    pub total: u64,
+}
+
+// "some avg10=0.00 avg60=0.00 avg300=0.00 total=0"
+pub fn parse(content: &str, kind: &str) -> Option<Stall> {
+    let line = content.lines().find(|line| line.starts_with(kind))?;
+
+    let mut stall = Stall::default();
+    for (name, value) in line
+        .split_whitespace()
+        .filter_map(|field| field.split_once('='))
+    {
+        match name {
+            "avg10" => stall.avg10 = value.parse().ok()?,
+            "avg60" => stall.avg60 = value.parse().ok()?,
+            "avg300" => stall.avg300 = value.parse().ok()?,
+            "total" => stall.total = value.parse().ok()?,
+            _ => (),
+        }
+    }
+
+    Some(stall)
+}
+
+pub struct Pressure {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: Vec<(String, [Option<Stall>; 2])>,
+    curr: Vec<(String, [Option<Stall>; 2])>,
+    elapsed: f64,
+}
+
+impl Pressure {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: Vec::new(),
+            curr: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, [Option<Stall>; 2])>> {
+        let stalls = RESOURCES
+            .iter()
+            .filter_map(|resource| {
+                let content = fs::read_to_string(self.root.join("pressure").join(resource)).ok()?;
+
+                Some((
+                    resource.to_string(),
+                    [parse(&content, "some"), parse(&content, "full")],
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        // kernel without CONFIG_PSI
+        if stalls.is_empty() {
+            None
+        } else {
+            Some(stalls)
+        }
+    }
+}
+
+impl CounterSource for Pressure {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Pressure" {
+            return None;
+        }
+
+        let counter = COUNTERS
+            .iter()
+            .position(|(name, _, _)| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr);
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        let (_, kind, field) = COUNTERS[*counter];
+        let kind = (kind == "full") as usize;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let curr = curr[kind]?;
+
+                let value = match field {
+                    0 => curr.avg10,
+                    1 => curr.avg60,
+                    // total is in microseconds, report milliseconds stalled per second
+                    _ => {
+                        let (_, prev) =
+                            self.prev.iter().find(|(prev_name, _)| prev_name == name)?;
+                        if self.elapsed <= 0.0 {
+                            return None;
+                        }
+
+                        let delta = curr.total.saturating_sub(prev[kind]?.total);
+                        delta as f64 / 1000.0 / self.elapsed
+                    }
+                };
+
+                Some((name.clone(), value))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn parses_the_stall_lines() {
+        let content = "some avg10=1.50 avg60=0.75 avg300=0.25 total=123456\n\
+                       full avg10=0.50 avg60=0.00 avg300=0.00 total=789\n";
+
+        let some = parse(content, "some").unwrap();
+        assert_eq!(
+            (some.avg10, some.avg60, some.avg300, some.total),
+            (1.5, 0.75, 0.25, 123456)
+        );
+        assert_eq!(parse(content, "full").unwrap().total, 789);
+
+        // cpu has no full line before linux 5.13
+        assert!(parse("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n", "full").is_none());
+        assert!(parse("some avg10=x avg60=0.00 avg300=0.00 total=0\n", "some").is_none());
+    }
+
+    #[test]
+    fn stall_time_is_in_milliseconds_per_second() {
+        let root = env::temp_dir().join(format!("pdhv-pressure-{}", process::id()));
+        fs::create_dir_all(root.join("pressure")).unwrap();
+        let write_pressure = |cpu_total: u64, memory_total: u64| {
+            fs::write(
+                root.join("pressure").join("cpu"),
+                format!(
+                    "some avg10=2.00 avg60=1.00 avg300=0.50 total={}\n",
+                    cpu_total
+                ),
+            )
+            .unwrap();
+            fs::write(
+                root.join("pressure").join("memory"),
+                format!(
+                    "some avg10=0.00 avg60=0.00 avg300=0.00 total={}\n\
+                     full avg10=0.00 avg60=0.00 avg300=0.00 total={}\n",
+                    memory_total,
+                    memory_total / 2
+                ),
+            )
+            .unwrap();
+        };
+
+        let mut pressure = Pressure::new(&root);
+        let mut add = |path: &str| {
+            pressure
+                .add_counter(&CounterPath::parse(path).unwrap())
+                .unwrap()
+        };
+        let (some_avg10, some_stall, full_stall) = (
+            add(r"\Pressure(*)\% Some avg10"),
+            add(r"\Pressure(*)\Some Stall Time/sec"),
+            add(r"\Pressure(memory)\Full Stall Time/sec"),
+        );
+
+        assert!(!pressure.collect(time::Duration::from_secs(1)));
+
+        write_pressure(1_000_000, 0);
+        assert!(pressure.collect(time::Duration::from_secs(1)));
+        assert_eq!(pressure.get_values(some_stall), Some(Vec::new()));
+
+        write_pressure(1_100_000, 500_000);
+        assert!(pressure.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        // no io file, and no full line for the cpu
+        assert_eq!(
+            pressure.get_values(some_avg10),
+            Some(vec![("cpu".to_string(), 2.0), ("memory".to_string(), 0.0)])
+        );
+        assert_eq!(
+            pressure.get_values(some_stall),
+            Some(vec![
+                ("cpu".to_string(), 50.0),
+                ("memory".to_string(), 250.0)
+            ])
+        );
+        assert_eq!(
+            pressure.get_values(full_stall),
+            Some(vec![("memory".to_string(), 125.0)])
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+const COUNTERS: [&str; 5] = [
+    "% Processor Time",
+    "% User Time",
+    "% Privileged Time",
+    "% Idle Time",
+    "% IO Wait Time",
+];
+
+#[derive(Clone, Copy, Default)]
+struct Jiffies {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl Jiffies {
+    fn parse(fields: &[u64]) -> Self {
+        let field = |index: usize| fields.get(index).copied().unwrap_or(0);
+
+        Self {
+            user: field(0),
+            nice: field(1),
+            system: field(2),
+            idle: field(3),
+            iowait: field(4),
+            irq: field(5),
+            softirq: field(6),
+            steal: field(7),
+        }
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        Self {
+            user: self.user.saturating_sub(other.user),
+            nice: self.nice.saturating_sub(other.nice),
+            system: self.system.saturating_sub(other.system),
+            idle: self.idle.saturating_sub(other.idle),
+            iowait: self.iowait.saturating_sub(other.iowait),
+            irq: self.irq.saturating_sub(other.irq),
+            softirq: self.softirq.saturating_sub(other.softirq),
+            steal: self.steal.saturating_sub(other.steal),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+
+    fn percent(&self, counter: usize) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+
+        let part = match counter {
+            0 => total - self.idle - self.iowait,
+            1 => self.user + self.nice,
+            2 => self.system + self.irq + self.softirq,
+            3 => self.idle,
+            _ => self.iowait,
+        };
+
+        100.0 * part as f64 / total as f64
+    }
+}
+
+pub struct ProcStat {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: Vec<(String, Jiffies)>,
+    curr: Vec<(String, Jiffies)>,
+}
+
+impl ProcStat {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: Vec::new(),
+            curr: Vec::new(),
+        }
+    }
+
+    fn read(&self) -> Option<Vec<(String, Jiffies)>> {
+        let stat = fs::read_to_string(self.root.join("stat")).ok()?;
+
+        let mut cpus = Vec::new();
+        let mut total = None;
+
+        for line in stat.lines().filter(|line| line.starts_with("cpu")) {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            let jiffies = Jiffies::parse(
+                fields
+                    .map(|field| field.parse::<u64>().unwrap_or(0))
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+            );
+
+            match name.strip_prefix("cpu") {
+                Some("") => total = Some(("_Total".to_string(), jiffies)),
+                Some(index) => cpus.push((index.to_string(), jiffies)),
+                None => (),
+            }
+        }
+
+        cpus.extend(total);
+        Some(cpus)
+    }
+}
+
+impl CounterSource for ProcStat {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Processor" {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        if self.prev.is_empty() {
+            return None;
+        }
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let (_, prev) = self.prev.iter().find(|(prev_name, _)| prev_name == name)?;
+
+                Some((name.clone(), curr.sub(prev).percent(*counter)))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    #[test]
+    fn percentages_come_from_the_jiffies() {
+        let root = env::temp_dir().join(format!("pdhv-proc-stat-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let write_stat = |stat: &str| fs::write(root.join("stat"), stat).unwrap();
+
+        let mut proc_stat = ProcStat::new(&root);
+        let processor = proc_stat
+            .add_counter(&CounterPath::parse(r"\Processor(*)\% Processor Time").unwrap())
+            .unwrap();
+        let user = proc_stat
+            .add_counter(&CounterPath::parse(r"\Processor(1)\% User Time").unwrap())
+            .unwrap();
+        let total = proc_stat
+            .add_counter(&CounterPath::parse(r"\Processor(_Total)\% IO Wait Time").unwrap())
+            .unwrap();
+        assert_eq!(
+            proc_stat.add_counter(&CounterPath::parse(r"\Processor(*)\Interrupts/sec").unwrap()),
+            None
+        );
+
+        write_stat(
+            "cpu  100 0 100 800 0 0 0 0 0 0\n\
+             cpu0 50 0 50 400 0 0 0 0 0 0\n\
+             cpu1 50 0 50 400 0 0 0 0 0 0\n\
+             intr 12345\n",
+        );
+        assert!(proc_stat.collect(time::Duration::from_secs(1)));
+        assert_eq!(proc_stat.get_values(processor), None);
+
+        // cpu0 is busy, cpu1 waits on io a quarter of the time
+        write_stat(
+            "cpu  250 50 150 900 50 0 0 0 0 0\n\
+             cpu0 150 50 100 400 0 0 0 0 0 0\n\
+             cpu1 100 0 50 500 50 0 0 0 0 0\n\
+             intr 12345\n",
+        );
+        assert!(proc_stat.collect(time::Duration::from_secs(1)));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            proc_stat.get_values(processor),
+            Some(vec![
+                ("0".to_string(), 100.0),
+                ("1".to_string(), 25.0),
+                ("_Total".to_string(), 62.5)
+            ])
+        );
+        assert_eq!(
+            proc_stat.get_values(user),
+            Some(vec![("1".to_string(), 25.0)])
+        );
+        assert_eq!(
+            proc_stat.get_values(total),
+            Some(vec![("_Total".to_string(), 12.5)])
+        );
+
+        assert!(!proc_stat.collect(time::Duration::from_secs(1)));
+    }
+}
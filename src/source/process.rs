@@ -0,0 +1,278 @@
+use std::{collections, fs, mem, path, time};
+
+use super::{CounterPath, CounterSource};
+
+// USER_HZ, clock ticks per second used by /proc/[pid]/stat
+const CLOCK_TICKS: f64 = 100.0;
+
+const COUNTERS: [&str; 6] = [
+    "% Processor Time",
+    "Working Set",
+    "Thread Count",
+    "IO Read Bytes/sec",
+    "IO Write Bytes/sec",
+    "IO Data Bytes/sec",
+];
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    ticks: u64,
+    rss: u64,
+    threads: u64,
+    io: Option<(u64, u64)>,
+}
+
+pub struct Process {
+    root: path::PathBuf,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    prev: collections::HashMap<String, Stats>,
+    curr: Vec<(String, Stats)>,
+    elapsed: f64,
+}
+
+impl Process {
+    pub fn new(root: impl Into<path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            prev: collections::HashMap::new(),
+            curr: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    fn read_process(&self, pid: u32) -> Option<(String, Stats)> {
+        let dir = self.root.join(pid.to_string());
+
+        // comm may contain spaces and parentheses, it ends at the last ')'
+        let stat = fs::read_to_string(dir.join("stat")).ok()?;
+        let (head, tail) = stat.rsplit_once(')')?;
+        let (_, comm) = head.split_once('(')?;
+        let fields = tail.split_whitespace().collect::<Vec<_>>();
+        let ticks = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+
+        let status = fs::read_to_string(dir.join("status")).ok()?;
+        let status_field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.split_whitespace().next())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+
+        // io is only readable for our own processes without privileges
+        let io = fs::read_to_string(dir.join("io")).ok().and_then(|io| {
+            let io_field = |name: &str| {
+                io.lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+            };
+
+            Some((io_field("rchar:")?, io_field("wchar:")?))
+        });
+
+        Some((
+            format!("{}#{}", comm, pid),
+            Stats {
+                ticks,
+                rss: status_field("VmRSS:").unwrap_or(0) * 1024,
+                threads: status_field("Threads:").unwrap_or(0),
+                io,
+            },
+        ))
+    }
+
+    fn read(&self) -> Option<Vec<(String, Stats)>> {
+        let mut pids = fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .collect::<Vec<_>>();
+        pids.sort_unstable();
+
+        // processes may exit while we walk /proc
+        Some(
+            pids.into_iter()
+                .filter_map(|pid| self.read_process(pid))
+                .collect(),
+        )
+    }
+}
+
+impl CounterSource for Process {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Process" {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        if self.counters.is_empty() {
+            return true;
+        }
+
+        match self.read() {
+            Some(curr) => {
+                self.prev = mem::replace(&mut self.curr, curr).into_iter().collect();
+                self.elapsed = elapsed.as_secs_f64();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+        if self.prev.is_empty() || self.elapsed <= 0.0 {
+            return None;
+        }
+
+        // processes that were not there at the previous sample have no rate yet
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.iter().filter_map(|(name, curr)| {
+                let prev = self.prev.get(name)?;
+                let io = |read: bool, write: bool| {
+                    let ((curr_read, curr_write), (prev_read, prev_write)) = (curr.io?, prev.io?);
+                    let delta = read as u64 * curr_read.saturating_sub(prev_read)
+                        + write as u64 * curr_write.saturating_sub(prev_write);
+
+                    Some(delta as f64 / self.elapsed)
+                };
+
+                let value = match counter {
+                    0 => {
+                        100.0 * curr.ticks.saturating_sub(prev.ticks) as f64
+                            / CLOCK_TICKS
+                            / self.elapsed
+                    }
+                    1 => curr.rss as f64,
+                    2 => curr.threads as f64,
+                    3 => io(true, false)?,
+                    4 => io(false, true)?,
+                    _ => io(true, true)?,
+                };
+
+                Some((name.clone(), value))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    fn write_process(
+        root: &path::Path,
+        pid: u32,
+        comm: &str,
+        ticks: (u64, u64),
+        io: Option<(u64, u64)>,
+    ) {
+        let dir = root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(
+            dir.join("stat"),
+            format!(
+                "{} ({}) S 1 {} {} 0 -1 4194560 100 0 0 0 {} {} 0 0 20 0 3 0 42 1000000 512\n",
+                pid, comm, pid, pid, ticks.0, ticks.1
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("status"),
+            format!("Name:\t{}\nVmRSS:\t    2048 kB\nThreads:\t3\n", comm),
+        )
+        .unwrap();
+        if let Some((rchar, wchar)) = io {
+            fs::write(
+                dir.join("io"),
+                format!("rchar: {}\nwchar: {}\nsyscr: 10\nsyscw: 5\n", rchar, wchar),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn reads_the_processes() {
+        let root = env::temp_dir().join(format!("pdhv-process-{}", process::id()));
+        fs::create_dir_all(root.join("self")).unwrap();
+        fs::write(root.join("uptime"), "1.0 1.0\n").unwrap();
+
+        let mut process = Process::new(&root);
+        let mut add = |path: &str| {
+            process
+                .add_counter(&CounterPath::parse(path).unwrap())
+                .unwrap()
+        };
+        let (processor, working_set, threads, read, write, data) = (
+            add(r"\Process(*)\% Processor Time"),
+            add(r"\Process(*)\Working Set"),
+            add(r"\Process(Web (Content) x#42)\Thread Count"),
+            add(r"\Process(*)\IO Read Bytes/sec"),
+            add(r"\Process(systemd#1)\IO Write Bytes/sec"),
+            add(r"\Process(systemd*)\IO Data Bytes/sec"),
+        );
+
+        // no io file for 42, like the processes of other users
+        write_process(&root, 1, "systemd", (100, 50), Some((1000, 500)));
+        write_process(&root, 42, "Web (Content) x", (10, 0), None);
+        assert!(process.collect(time::Duration::from_secs(1)));
+        assert_eq!(process.get_values(processor), None);
+
+        write_process(&root, 1, "systemd", (200, 150), Some((5000, 2500)));
+        write_process(&root, 42, "Web (Content) x", (40, 20), None);
+        write_process(&root, 77, "bash", (5, 5), Some((0, 0)));
+        assert!(process.collect(time::Duration::from_secs(2)));
+        fs::remove_dir_all(&root).unwrap();
+
+        // 77 only has a value from the next sample
+        assert_eq!(
+            process.get_values(processor),
+            Some(vec![
+                ("systemd#1".to_string(), 100.0),
+                ("Web (Content) x#42".to_string(), 25.0)
+            ])
+        );
+        assert_eq!(
+            process.get_values(working_set),
+            Some(vec![
+                ("systemd#1".to_string(), 2048.0 * 1024.0),
+                ("Web (Content) x#42".to_string(), 2048.0 * 1024.0)
+            ])
+        );
+        assert_eq!(
+            process.get_values(threads),
+            Some(vec![("Web (Content) x#42".to_string(), 3.0)])
+        );
+        assert_eq!(
+            process.get_values(read),
+            Some(vec![("systemd#1".to_string(), 2000.0)])
+        );
+        assert_eq!(
+            process.get_values(write),
+            Some(vec![("systemd#1".to_string(), 1000.0)])
+        );
+        assert_eq!(
+            process.get_values(data),
+            Some(vec![("systemd#1".to_string(), 3000.0)])
+        );
+    }
+}
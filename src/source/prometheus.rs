@@ -0,0 +1,429 @@
+use std::{
+    collections,
+    io::{Read, Write},
+    net::{self, ToSocketAddrs},
+    sync::{mpsc, Arc, Mutex},
+    thread, time,
+};
+
+use super::{CounterPath, CounterSource};
+
+const SCRAPE_TIMEOUT: time::Duration = time::Duration::from_millis(800);
+// in collections, a scrape older than that has no data
+const SCRAPE_EXPIRY: u32 = 3;
+
+const SUFFIXES: [&str; 6] = ["_total", "_bucket", "_sum", "_count", "_created", "_info"];
+
+type Metric = (String, bool, Vec<(String, f64)>);
+
+#[derive(Clone)]
+struct Scrape {
+    instant: time::Instant,
+    // metric name -> (family, is cumulative, instances)
+    metrics: collections::HashMap<String, Metric>,
+}
+
+// http://host:port/path -> (host:port, host, path)
+fn parse_url(url: &str) -> Option<(String, String, String)> {
+    let url = url.strip_prefix("http://")?;
+    let (authority, path) = match url.find('/') {
+        Some(index) => (&url[..index], &url[index..]),
+        None => (url, "/metrics"),
+    };
+
+    let host = authority.split(':').next()?;
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    Some((address, host.to_string(), path.to_string()))
+}
+
+// http/1.0 so the body is never chunked
+fn get(address: &str, host: &str, path: &str) -> Option<String> {
+    let mut stream =
+        net::TcpStream::connect_timeout(&address.to_socket_addrs().ok()?.next()?, SCRAPE_TIMEOUT)
+            .ok()?;
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT)).ok()?;
+
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: text/plain\r\n\r\n",
+        path, host
+    )
+    .ok()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    if head.split_whitespace().nth(1)? != "200" {
+        return None;
+    }
+
+    Some(body.to_string())
+}
+
+// name{label="value",...} value [timestamp]
+fn parse_sample(line: &str) -> Option<(String, String, f64)> {
+    let (name, rest) = match line.find(|c: char| c == '{' || c.is_whitespace()) {
+        Some(index) => line.split_at(index),
+        None => return None,
+    };
+
+    let mut labels = Vec::new();
+    let mut rest = rest.trim_start();
+
+    if let Some(mut inner) = rest.strip_prefix('{') {
+        loop {
+            inner = inner.trim_start_matches([',', ' ']);
+            if let Some(after) = inner.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (label, after) = inner.split_once('=')?;
+            let mut chars = after.strip_prefix('"')?.char_indices();
+            let mut value = String::new();
+
+            let end = loop {
+                match chars.next()? {
+                    (index, '"') => break index,
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        escaped => value.push(escaped),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+
+            labels.push(format!("{}={}", label.trim(), value));
+            inner = &after[end + 2..];
+        }
+    }
+
+    let value = rest.split_whitespace().next()?.parse::<f64>().ok()?;
+
+    Some((name.to_string(), labels.join(","), value))
+}
+
+fn parse(body: &str) -> collections::HashMap<String, Metric> {
+    let mut types = collections::HashMap::new();
+    let mut metrics = collections::HashMap::<String, Metric>::new();
+
+    for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut fields = comment.split_whitespace();
+            if let (Some("TYPE"), Some(family), Some(kind)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                types.insert(family.to_string(), kind.to_string());
+            }
+
+            continue;
+        }
+
+        if let Some((name, instance, value)) = parse_sample(line) {
+            let family = family(&name, |family| types.contains_key(family));
+            // cumulative values are charted as a rate, summary quantiles are not
+            let is_cumulative = match types.get(family).map(String::as_str) {
+                Some("counter") => true,
+                Some("histogram") | Some("summary") => name != family,
+                _ => false,
+            };
+
+            let family = family.to_string();
+            metrics
+                .entry(name)
+                .or_insert((family, is_cumulative, Vec::new()))
+                .2
+                .push((instance, value));
+        }
+    }
+
+    metrics
+}
+
+// the family of a sample is its name, without a known suffix when the family was declared
+fn family(name: &str, is_declared: impl Fn(&str) -> bool) -> &str {
+    if is_declared(name) {
+        return name;
+    }
+
+    SUFFIXES
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix))
+        .find(|family| is_declared(family))
+        .unwrap_or(name)
+}
+
+pub struct Prometheus {
+    address: String,
+    // a scrape per collection, the thread is gone with the source
+    tx: mpsc::Sender<()>,
+    scraped: Arc<Mutex<Option<Scrape>>>,
+    prev: Option<Scrape>,
+    curr: Option<Scrape>,
+    counters: collections::HashMap<usize, (Option<String>, String)>,
+    last_id: usize,
+}
+
+impl Prometheus {
+    pub fn new(url: &str) -> Option<Self> {
+        let (address, host, path) = parse_url(url)?;
+
+        let scraped = Arc::new(Mutex::new(None));
+        let thread_scraped = scraped.clone();
+        let thread_address = address.clone();
+
+        let (tx, rx) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            while rx.recv().is_ok() {
+                // the collections that came during a slow scrape are scraped once
+                while rx.try_recv().is_ok() {}
+
+                if let Some(body) = get(&thread_address, &host, &path) {
+                    *thread_scraped.lock().unwrap() = Some(Scrape {
+                        instant: time::Instant::now(),
+                        metrics: parse(&body),
+                    });
+                }
+            }
+        });
+
+        Some(Self {
+            address,
+            tx,
+            scraped,
+            prev: None,
+            curr: None,
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        })
+    }
+}
+
+impl CounterSource for Prometheus {
+    // \\host:port\family(label=value,...)\metric
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.machine.as_deref() != Some(self.address.as_str())
+            || !path.counter.starts_with(path.object.as_str())
+        {
+            return None;
+        }
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), path.counter.clone()));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    // the metrics of the last scrape, under their family: a histogram is one object
+    fn offers(&self) -> Vec<CounterPath> {
+        let mut names = self
+            .curr
+            .iter()
+            .flat_map(|scrape| scrape.metrics.iter())
+            .map(|(name, (family, _, _))| (family, name))
+            .collect::<Vec<_>>();
+        names.sort();
+
+        names
+            .into_iter()
+            .filter_map(|(family, name)| {
+                CounterPath::parse(&format!(r"\\{}\{}\{}", self.address, family, name))
+            })
+            .collect()
+    }
+
+    // scraped at the pace of the collections, the values are the ones of the last scrape
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        let _ = self.tx.send(());
+
+        if let Some(scrape) = self.scraped.lock().unwrap().take() {
+            self.prev = self.curr.replace(scrape);
+        }
+
+        // the endpoint stopped answering
+        if !elapsed.is_zero()
+            && self
+                .curr
+                .as_ref()
+                .is_some_and(|curr| curr.instant.elapsed() > elapsed * SCRAPE_EXPIRY)
+        {
+            self.prev = None;
+            self.curr = None;
+        }
+
+        self.curr.is_some()
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, metric) = self.counters.get(&hcounter)?;
+        let curr = self.curr.as_ref()?;
+        let (_, is_cumulative, instances) = curr.metrics.get(metric)?;
+
+        if !is_cumulative {
+            return Some(super::filter_instances(
+                instance.as_deref(),
+                instances.iter().cloned(),
+            ));
+        }
+
+        let prev = self.prev.as_ref()?;
+        let (_, _, prev_instances) = prev.metrics.get(metric)?;
+        let elapsed = curr.instant.duration_since(prev.instant).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        // a counter going down was reset, it counts from zero again
+        Some(super::filter_instances(
+            instance.as_deref(),
+            instances.iter().filter_map(|(name, value)| {
+                let (_, prev_value) = prev_instances
+                    .iter()
+                    .find(|(prev_name, _)| prev_name == name)?;
+                let delta = if value >= prev_value {
+                    value - prev_value
+                } else {
+                    *value
+                };
+
+                Some((name.clone(), delta / elapsed))
+            }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{self, BufRead};
+
+    // answers the scrapes with requests_total going up by 10 and a fixed gauge, while is_up
+    fn stub(is_up: Arc<Mutex<bool>>) -> String {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for (scrape, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+
+                // the whole request, closing with unread data would reset the connection
+                let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|len| len > 2) {
+                    line.clear();
+                }
+
+                if !*is_up.lock().unwrap() {
+                    let _ = write!(stream, "HTTP/1.0 503 Service Unavailable\r\n\r\n");
+                    continue;
+                }
+
+                let _ = write!(
+                    stream,
+                    "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n\
+                     # TYPE requests counter\n\
+                     requests_total{{code=\"200\"}} {}\n\
+                     # TYPE temperature gauge\n\
+                     temperature 21.5\n",
+                    scrape * 10
+                );
+            }
+        });
+
+        address
+    }
+
+    // polls the collection until the counter has values
+    fn wait_for(prometheus: &mut Prometheus, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        (0..100).find_map(|_| {
+            thread::sleep(time::Duration::from_millis(20));
+            prometheus.collect(time::Duration::from_millis(20));
+
+            prometheus.get_values(hcounter)
+        })
+    }
+
+    fn add_counter(prometheus: &mut Prometheus, address: &str, path: &str) -> usize {
+        let path = CounterPath::parse(&format!(r"\\{}\{}", address, path)).unwrap();
+        prometheus.add_counter(&path).unwrap()
+    }
+
+    #[test]
+    fn scrapes_gauges_and_rates() {
+        let address = stub(Arc::new(Mutex::new(true)));
+        let mut prometheus = Prometheus::new(&format!("http://{}/metrics", address)).unwrap();
+        let temperature = add_counter(&mut prometheus, &address, r"temperature\temperature");
+        let requests = add_counter(&mut prometheus, &address, r"requests(*)\requests_total");
+
+        assert_eq!(
+            wait_for(&mut prometheus, temperature),
+            Some(vec![(String::new(), 21.5)])
+        );
+
+        // 10 requests between two scrapes, at most a second apart
+        let rates = wait_for(&mut prometheus, requests).unwrap();
+        assert_eq!(rates[0].0, "code=200");
+        assert!(rates[0].1 >= 10.0);
+    }
+
+    #[test]
+    fn old_scrapes_have_no_data() {
+        let is_up = Arc::new(Mutex::new(true));
+        let address = stub(is_up.clone());
+        let mut prometheus = Prometheus::new(&format!("http://{}", address)).unwrap();
+        let temperature = add_counter(&mut prometheus, &address, r"temperature\temperature");
+        assert!(wait_for(&mut prometheus, temperature).is_some());
+
+        *is_up.lock().unwrap() = false;
+        thread::sleep(time::Duration::from_millis(100));
+        assert!(!prometheus.collect(time::Duration::from_millis(20)));
+        assert_eq!(prometheus.get_values(temperature), None);
+    }
+
+    #[test]
+    fn metrics_are_offered_under_their_family() {
+        let mut prometheus = Prometheus::new("http://127.0.0.1:9").unwrap();
+        prometheus.curr = Some(Scrape {
+            instant: time::Instant::now(),
+            metrics: parse(
+                "# TYPE latency histogram\n\
+                 latency_bucket{le=\"0.1\"} 3\n\
+                 latency_bucket{le=\"+Inf\"} 4\n\
+                 latency_sum 0.5\n\
+                 latency_count 4\n\
+                 # TYPE requests counter\n\
+                 requests_total 10\n\
+                 undeclared_total 1\n",
+            ),
+        });
+
+        let path = |family: &str, name: &str| {
+            CounterPath::parse(&format!(r"\\127.0.0.1:9\{}\{}", family, name)).unwrap()
+        };
+        assert_eq!(
+            prometheus.offers(),
+            [
+                path("latency", "latency_bucket"),
+                path("latency", "latency_count"),
+                path("latency", "latency_sum"),
+                path("requests", "requests_total"),
+                path("undeclared_total", "undeclared_total"),
+            ]
+        );
+        for path in prometheus.offers() {
+            assert!(prometheus.add_counter(&path).is_some(), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections, fs, path,
+    sync::{Arc, Mutex},
+    time,
+};
+
+use super::{CounterPath, CounterSource};
+
+type Frame = collections::HashMap<CounterPath, Vec<(String, f64)>>;
+
+pub struct Playback {
+    pub paused: bool,
+    pub speed: f64,
+    position: f64,
+    duration: f64,
+}
+
+impl Playback {
+    pub fn seek(&mut self, delta: f64) {
+        self.position = (self.position + delta).clamp(0.0, self.duration);
+    }
+}
+
+pub struct Replay {
+    name: String,
+    paths: Vec<CounterPath>,
+    // in seconds since the first frame, which is at start
+    start: f64,
+    frames: Vec<(f64, Frame)>,
+    frame: usize,
+    counters: collections::HashMap<usize, CounterPath>,
+    last_id: usize,
+    playback: Arc<Mutex<Playback>>,
+}
+
+// days from 0000-03-01 to 1970-01-01
+const UNIX_EPOCH_DAYS: i64 = 719468;
+
+// "D2023-2-14" "T9:5:3.45", fields are not zero padded and 45 is in milliseconds
+fn parse_time(date: &str, time: &str) -> Option<f64> {
+    let mut date = date.strip_prefix('D')?.split('-');
+    let (year, month, day) = (
+        date.next()?.parse::<i64>().ok()?,
+        date.next()?.parse::<i64>().ok()?,
+        date.next()?.parse::<i64>().ok()?,
+    );
+
+    let (time, millis) = time.strip_prefix('T')?.split_once('.')?;
+    let mut time = time.split(':');
+    let (hour, minute, second) = (
+        time.next()?.parse::<i64>().ok()?,
+        time.next()?.parse::<i64>().ok()?,
+        time.next()?.parse::<i64>().ok()?,
+    );
+
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - UNIX_EPOCH_DAYS;
+
+    Some(
+        (days * 86400 + hour * 3600 + minute * 60 + second) as f64
+            + millis.parse::<i64>().ok()? as f64 / 1000.0,
+    )
+}
+
+// D2023-2-14 T9:5:3.45 ; \object(*)\counter ; (a, 1.5) ; (b, 2) ; \object\counter ; (no data) ;
+fn parse_line(line: &str) -> Option<(f64, Frame, Vec<CounterPath>)> {
+    let mut fields = line.split(" ; ");
+    let (date, time) = fields.next()?.split_once(' ')?;
+    let time = parse_time(date, time)?;
+
+    let mut frame = Frame::new();
+    let mut paths = Vec::new();
+    let mut current = None;
+
+    for field in fields.map(|field| field.trim_end_matches(char::from(0))) {
+        if field.is_empty() || field == "(no data)" {
+            continue;
+        }
+
+        match field
+            .strip_prefix('(')
+            .and_then(|field| field.strip_suffix(')'))
+        {
+            Some(instance) => {
+                // (name, 1288490188.8 = 1.2 GiB) when the counter has a unit
+                let (name, value) = instance.rsplit_once(", ")?;
+                let value = value.split_once(" = ").map_or(value, |(value, _)| value);
+                frame
+                    .get_mut(current.as_ref()?)?
+                    .push((name.to_string(), value.parse().ok()?));
+            }
+            None => {
+                let path = CounterPath::parse(field)?.with_machine(None);
+                frame.insert(path.clone(), Vec::new());
+                paths.push(path.clone());
+                current = Some(path);
+            }
+        }
+    }
+
+    frame.retain(|_, instances| !instances.is_empty());
+
+    Some((time, frame, paths))
+}
+
+impl Replay {
+    pub fn open(file: &path::Path) -> Option<Self> {
+        let content = fs::read_to_string(file).ok()?;
+
+        let mut paths = Vec::<CounterPath>::new();
+        let mut frames = Vec::<(f64, Frame)>::new();
+
+        // the first line is the header
+        for (time, mut frame, line_paths) in content.lines().skip(1).filter_map(parse_line) {
+            // counters with a longer interval are not on every line, they keep their last value
+            if let Some((_, previous)) = frames.last() {
+                for (path, instances) in previous.iter() {
+                    if !line_paths.contains(path) {
+                        frame.insert(path.clone(), instances.clone());
+                    }
+                }
+            }
+
+            for path in line_paths {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+
+            frames.push((time, frame));
+        }
+
+        let start = frames.first()?.0;
+        for (time, _) in frames.iter_mut() {
+            *time -= start;
+        }
+
+        Some(Self {
+            name: file.file_stem()?.to_string_lossy().into(),
+            paths,
+            start,
+            playback: Arc::new(Mutex::new(Playback {
+                paused: false,
+                speed: 1.0,
+                position: 0.0,
+                duration: frames.last()?.0,
+            })),
+            frames,
+            frame: 0,
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        })
+    }
+
+    pub fn playback(&self) -> Arc<Mutex<Playback>> {
+        self.playback.clone()
+    }
+
+    // the recorded counters, on a machine named after the log
+    pub fn paths(&self) -> impl Iterator<Item = CounterPath> + '_ {
+        self.paths
+            .iter()
+            .map(|path| path.with_machine(Some(&self.name)))
+    }
+}
+
+impl CounterSource for Replay {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.machine.as_deref() != Some(self.name.as_str()) {
+            return None;
+        }
+
+        let path = path.with_machine(None);
+        if !self.paths.contains(&path) {
+            return None;
+        }
+
+        self.last_id += 1;
+        self.counters.insert(self.last_id, path);
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        let mut playback = self.playback.lock().unwrap();
+
+        if !playback.paused {
+            let speed = playback.speed;
+            playback.seek(elapsed.as_secs_f64() * speed);
+        }
+
+        self.frame = self
+            .frames
+            .partition_point(|(time, _)| *time <= playback.position)
+            .saturating_sub(1);
+
+        true
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let path = self.counters.get(&hcounter)?;
+
+        self.frames.get(self.frame)?.1.get(path).cloned()
+    }
+
+    // the local time of the log, read as utc
+    fn timestamp(&self) -> Option<u64> {
+        let (time, _) = self.frames.get(self.frame)?;
+
+        Some(((self.start + time) * 1000.0).max(0.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    // as written by the log of the query, a line per deadline after the header
+    const LOG: &str = "copyright pdhv.fr
+D2023-2-14 T9:5:3.0 ; \\Memory\\Available Bytes ; (, 1288490188.8 = 1.2 GiB) ; \\Processor(*)\\% Processor Time ; (0, 12.5) ; (_Total, 10) ; 
+D2023-2-14 T9:5:4.0 ; \\Processor(*)\\% Processor Time ; (0, 20) ; (_Total, 15) ; 
+D2023-2-14 T9:5:5.0 ; \\Processor(*)\\% Processor Time ; (no data) ; 
+D2023-2-14 T9:5:6.500 ; \\Memory\\Available Bytes ; (, 1073741824 = 1 GiB) ; \\Processor(*)\\% Processor Time ; (0, 30) ; (_Total, 25) ; 
+";
+
+    fn path(path: &str) -> CounterPath {
+        CounterPath::parse(path).unwrap()
+    }
+
+    #[test]
+    fn times_are_local_dates() {
+        assert_eq!(parse_time("D1970-1-1", "T0:0:0.0"), Some(0.0));
+        assert_eq!(parse_time("D2000-3-1", "T0:0:0.0"), Some(951868800.0));
+        assert_eq!(
+            parse_time("D2023-2-14", "T9:5:3.45"),
+            Some(1676365503.0 + 0.045)
+        );
+
+        assert_eq!(parse_time("2023-2-14", "T9:5:3.45"), None);
+        assert_eq!(parse_time("D2023-2-14", "T9:5:3"), None);
+        assert_eq!(parse_time("D2023-2", "T9:5:3.45"), None);
+    }
+
+    #[test]
+    fn lines_hold_the_counters_and_their_instances() {
+        let (time, frame, paths) = parse_line(LOG.lines().nth(1).unwrap()).unwrap();
+
+        assert_eq!(time, 1676365503.0);
+        assert_eq!(
+            paths,
+            [
+                path(r"\Memory\Available Bytes"),
+                path(r"\Processor(*)\% Processor Time")
+            ]
+        );
+        // the formatted value is skipped
+        assert_eq!(
+            frame[&path(r"\Memory\Available Bytes")],
+            [("".to_string(), 1288490188.8)]
+        );
+        assert_eq!(
+            frame[&path(r"\Processor(*)\% Processor Time")],
+            [("0".to_string(), 12.5), ("_Total".to_string(), 10.0)]
+        );
+
+        // a counter without data is listed, the machine is dropped
+        let (_, frame, paths) = parse_line(
+            r"D2023-2-14 T9:5:5.0 ; \\host\Processor(*)\% Processor Time ; (no data) ; ",
+        )
+        .unwrap();
+        assert_eq!(paths, [path(r"\Processor(*)\% Processor Time")]);
+        assert!(frame.is_empty());
+
+        assert!(parse_line("copyright pdhv.fr").is_none());
+        assert!(parse_line(r"D2023-2-14 T9:5:3.0 ; (0, 1) ; ").is_none());
+        assert!(parse_line(r"D2023-2-14 T9:5:3.0 ; \Memory\Available Bytes ; (, x) ; ").is_none());
+    }
+
+    #[test]
+    fn replays_follow_the_playback() {
+        let file = env::temp_dir().join(format!("pdhv-replay-{}.log", process::id()));
+        fs::write(&file, LOG).unwrap();
+        let replay = Replay::open(&file);
+        fs::remove_file(&file).unwrap();
+        let mut replay = replay.unwrap();
+
+        let name = format!("pdhv-replay-{}", process::id());
+        let memory = path(r"\Memory\Available Bytes").with_machine(Some(&name));
+        let processor = path(r"\Processor(*)\% Processor Time").with_machine(Some(&name));
+        assert_eq!(
+            replay.paths().collect::<Vec<_>>(),
+            [memory.clone(), processor.clone()]
+        );
+
+        assert_eq!(replay.add_counter(&memory.with_machine(None)), None);
+        let memory = replay.add_counter(&memory).unwrap();
+        let processor = replay.add_counter(&processor).unwrap();
+        let playback = replay.playback();
+        let memory_at = |replay: &Replay| replay.get_values(memory).map(|values| values[0].1);
+        let processor_at = |replay: &Replay| replay.get_values(processor).map(|values| values[0].1);
+
+        replay.collect(time::Duration::ZERO);
+        assert_eq!(replay.timestamp(), Some(1676365503000));
+        assert_eq!(memory_at(&replay), Some(1288490188.8));
+        assert_eq!(processor_at(&replay), Some(12.5));
+
+        // the memory is not on this line, it keeps its value
+        replay.collect(time::Duration::from_secs(1));
+        assert_eq!(memory_at(&replay), Some(1288490188.8));
+        assert_eq!(processor_at(&replay), Some(20.0));
+
+        playback.lock().unwrap().paused = true;
+        replay.collect(time::Duration::from_secs(5));
+        assert_eq!(processor_at(&replay), Some(20.0));
+
+        // the missed deadline has no data
+        {
+            let mut playback = playback.lock().unwrap();
+            playback.paused = false;
+            playback.speed = 0.5;
+        }
+        replay.collect(time::Duration::from_secs(2));
+        assert_eq!(replay.timestamp(), Some(1676365505000));
+        assert_eq!(memory_at(&replay), Some(1288490188.8));
+        assert_eq!(processor_at(&replay), None);
+
+        // seeking stops at the last frame
+        playback.lock().unwrap().seek(10.0);
+        replay.collect(time::Duration::ZERO);
+        assert_eq!(replay.timestamp(), Some(1676365506500));
+        assert_eq!(memory_at(&replay), Some(1073741824.0));
+        assert_eq!(processor_at(&replay), Some(30.0));
+
+        playback.lock().unwrap().seek(-100.0);
+        replay.collect(time::Duration::ZERO);
+        assert_eq!(processor_at(&replay), Some(12.5));
+    }
+}
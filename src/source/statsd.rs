@@ -0,0 +1,323 @@
+use std::{
+    collections, mem, net,
+    sync::{
+        mpsc::{self, TryRecvError},
+        Arc, Mutex,
+    },
+    thread, time,
+};
+
+use super::{CounterPath, CounterSource};
+
+const RECV_TIMEOUT: time::Duration = time::Duration::from_millis(200);
+const TIMER_INSTANCES: [&str; 3] = ["min", "avg", "max"];
+
+#[derive(Default)]
+struct Pending {
+    counts: collections::HashMap<String, f64>,
+    // (is relative, value) in the order they were received
+    gauges: collections::HashMap<String, Vec<(bool, f64)>>,
+    timings: collections::HashMap<String, Vec<f64>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Count,
+    Gauge,
+    Timing,
+}
+
+// name:value|type[|@sample_rate][|#tags], one metric per line
+fn parse_line(
+    line: &str,
+    kinds: &mut collections::HashMap<String, Kind>,
+    pending: &mut Pending,
+) -> Option<()> {
+    let (name, rest) = line.trim().split_once(':')?;
+    let mut fields = rest.split('|');
+    let value = fields.next()?;
+    let parsed = value.parse::<f64>().ok()?;
+    let kind = match fields.next()? {
+        "c" => Kind::Count,
+        "g" => Kind::Gauge,
+        "ms" | "h" | "d" => Kind::Timing,
+        _ => return None,
+    };
+
+    // a name keeps the type it was first received with, they would overwrite each other
+    if *kinds.entry(name.to_string()).or_insert(kind) != kind {
+        return None;
+    }
+
+    let sample_rate = fields
+        .find_map(|field| field.strip_prefix('@'))
+        .and_then(|rate| rate.parse::<f64>().ok())
+        .filter(|rate| *rate > 0.0)
+        .unwrap_or(1.0);
+
+    match kind {
+        Kind::Count => {
+            *pending.counts.entry(name.to_string()).or_insert(0.0) += parsed / sample_rate;
+        }
+        Kind::Gauge => {
+            let is_relative = value.starts_with(['+', '-']);
+            pending
+                .gauges
+                .entry(name.to_string())
+                .or_default()
+                .push((is_relative, parsed));
+        }
+        Kind::Timing => {
+            pending
+                .timings
+                .entry(name.to_string())
+                .or_default()
+                .push(parsed);
+        }
+    }
+
+    Some(())
+}
+
+pub struct Statsd {
+    _tx: mpsc::Sender<()>,
+    pending: Arc<Mutex<Pending>>,
+    counts: collections::BTreeSet<String>,
+    gauges: collections::HashMap<String, f64>,
+    curr: collections::HashMap<String, Vec<(String, f64)>>,
+    counters: collections::HashMap<usize, (Option<String>, String)>,
+    last_id: usize,
+}
+
+impl Statsd {
+    pub fn bind(address: &str) -> Option<Self> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("0.0.0.0:{}", address)
+        };
+
+        let socket = net::UdpSocket::bind(address).ok()?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT)).ok()?;
+
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let thread_pending = pending.clone();
+
+        let (_tx, rx) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let mut buffer = [0; 65536];
+            let mut kinds = collections::HashMap::new();
+
+            loop {
+                if let Err(TryRecvError::Disconnected) = rx.try_recv() {
+                    break;
+                }
+
+                if let Ok(len) = socket.recv(&mut buffer) {
+                    let packet = String::from_utf8_lossy(&buffer[..len]);
+                    let mut pending = thread_pending.lock().unwrap();
+
+                    for line in packet.lines() {
+                        parse_line(line, &mut kinds, &mut pending);
+                    }
+                }
+            }
+        });
+
+        Some(Self {
+            _tx,
+            pending,
+            counts: collections::BTreeSet::new(),
+            gauges: collections::HashMap::new(),
+            curr: collections::HashMap::new(),
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        })
+    }
+}
+
+impl CounterSource for Statsd {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "StatsD" {
+            return None;
+        }
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), path.counter.clone()));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    // the metrics of the last collection
+    fn offers(&self) -> Vec<CounterPath> {
+        let mut names = self.curr.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+
+        super::paths_of("StatsD", names.into_iter())
+    }
+
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        let pending = mem::take(&mut *self.pending.lock().unwrap());
+
+        self.curr.clear();
+
+        // counters are zero once seen without packets
+        for (name, rate) in
+            super::per_second(&mut self.counts, &pending.counts, elapsed).unwrap_or_default()
+        {
+            self.curr.insert(name, vec![(String::new(), rate)]);
+        }
+
+        // gauges keep their last value
+        for (name, updates) in pending.gauges {
+            let gauge = self.gauges.entry(name).or_insert(0.0);
+            for (is_relative, value) in updates {
+                *gauge = if is_relative { *gauge + value } else { value };
+            }
+        }
+        for (name, gauge) in self.gauges.iter() {
+            self.curr
+                .insert(name.clone(), vec![(String::new(), *gauge)]);
+        }
+
+        for (name, timings) in pending.timings {
+            let min = timings.iter().copied().fold(f64::MAX, f64::min);
+            let max = timings.iter().copied().fold(f64::MIN, f64::max);
+            let avg = timings.iter().sum::<f64>() / timings.len() as f64;
+
+            self.curr.insert(
+                name,
+                TIMER_INSTANCES
+                    .iter()
+                    .map(|instance| instance.to_string())
+                    .zip([min, avg, max])
+                    .collect(),
+            );
+        }
+
+        true
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, name) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr.get(name)?.iter().cloned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> (Vec<bool>, Pending) {
+        let mut kinds = collections::HashMap::new();
+        let mut pending = Pending::default();
+
+        let parsed = lines
+            .iter()
+            .map(|line| parse_line(line, &mut kinds, &mut pending).is_some())
+            .collect();
+
+        (parsed, pending)
+    }
+
+    #[test]
+    fn lines_are_parsed_by_type() {
+        let (parsed, pending) = parse(&[
+            "hits:2|c|@0.5",
+            "hits:1|c|#region:eu",
+            "load:10|g",
+            "load:-3|g",
+            "load:+1|g",
+            "latency:10|ms",
+            "size:2.5|h",
+        ]);
+
+        assert!(parsed.iter().all(|parsed| *parsed));
+        assert_eq!(pending.counts["hits"], 5.0);
+        assert_eq!(
+            pending.gauges["load"],
+            [(false, 10.0), (true, -3.0), (true, 1.0)]
+        );
+        assert_eq!(pending.timings["latency"], [10.0]);
+        assert_eq!(pending.timings["size"], [2.5]);
+    }
+
+    #[test]
+    fn bad_lines_and_type_clashes_are_dropped() {
+        let (parsed, pending) = parse(&[
+            "hits",
+            "hits|c",
+            "hits:x|c",
+            "hits:1|s",
+            "hits:1|c",
+            "hits:1|g",
+            "hits:1|ms",
+        ]);
+
+        assert_eq!(parsed, [false, false, false, false, true, false, false]);
+        assert_eq!(pending.counts["hits"], 1.0);
+        assert!(pending.gauges.is_empty());
+        assert!(pending.timings.is_empty());
+    }
+
+    #[test]
+    fn packets_are_collected() {
+        // a free port for the source
+        let address = net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut statsd = Statsd::bind(&address.to_string()).unwrap();
+
+        let sender = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(
+                b"hits:2|c|@0.5\nhits:1|c\nload:3|g\nload:+1|g\nlatency:10|ms\nlatency:30|ms",
+                address,
+            )
+            .unwrap();
+
+        for _ in 0..100 {
+            if !statsd.pending.lock().unwrap().timings.is_empty() {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(20));
+        }
+
+        let path = |counter: &str| CounterPath::parse(&format!(r"\StatsD\{}", counter)).unwrap();
+        let hits = statsd.add_counter(&path("hits")).unwrap();
+        let load = statsd.add_counter(&path("load")).unwrap();
+        let latency = statsd.add_counter(&path("latency")).unwrap();
+
+        assert!(statsd.collect(time::Duration::from_secs(2)));
+        assert_eq!(
+            statsd.offers(),
+            [path("hits"), path("latency"), path("load")]
+        );
+        assert_eq!(statsd.get_values(hits), Some(vec![(String::new(), 2.5)]));
+        assert_eq!(statsd.get_values(load), Some(vec![(String::new(), 4.0)]));
+        assert_eq!(
+            statsd.get_values(latency),
+            Some(vec![
+                ("min".to_string(), 10.0),
+                ("avg".to_string(), 20.0),
+                ("max".to_string(), 30.0)
+            ])
+        );
+
+        // without packets counters are zero, gauges keep their value and timers are gone
+        assert!(statsd.collect(time::Duration::from_secs(1)));
+        assert_eq!(statsd.get_values(hits), Some(vec![(String::new(), 0.0)]));
+        assert_eq!(statsd.get_values(load), Some(vec![(String::new(), 4.0)]));
+        assert_eq!(statsd.get_values(latency), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections, f64::consts::PI, time};
+
+use super::{CounterPath, CounterSource};
+
+const COUNTERS: [&str; 4] = ["Sine", "Square", "Random Walk", "Spike"];
+
+// in ticks, one tick per collect
+const PERIOD: u64 = 20;
+const SPIKE_PROBABILITY: f64 = 0.05;
+
+pub struct Synthetic {
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+    churn: Option<u64>,
+    seed: u64,
+    tick: u64,
+    next_instance: usize,
+    // instance id, random walk, spike
+    instances: Vec<(usize, f64, f64)>,
+}
+
+impl Synthetic {
+    // every `churn` ticks the oldest instance disappears and a new one appears
+    pub fn new(nb_instance: usize, churn: Option<u64>, seed: u64) -> Self {
+        Self {
+            counters: collections::HashMap::new(),
+            last_id: 0,
+            churn,
+            // xorshift never leaves 0
+            seed: seed.max(1),
+            tick: 0,
+            next_instance: nb_instance,
+            instances: (0..nb_instance).map(|id| (id, 50.0, 0.0)).collect(),
+        }
+    }
+
+    // "instances[,churn[,seed]]", no source for 0 instances and no churn for a churn of 0
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut fields = spec.split(',').map(|field| field.trim().parse::<u64>());
+        let (nb_instance, churn, seed) = match (
+            fields.next().unwrap_or(Ok(0)),
+            fields.next().unwrap_or(Ok(0)),
+            fields.next().unwrap_or(Ok(1)),
+        ) {
+            (Ok(nb_instance), Ok(churn), Ok(seed)) => (nb_instance, churn, seed),
+            _ => {
+                eprintln!("Invalid synthetic counters {}", spec);
+                return None;
+            }
+        };
+
+        (nb_instance > 0)
+            .then(|| Self::new(nb_instance as usize, (churn > 0).then_some(churn), seed))
+    }
+
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+
+        (self.seed >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    fn value(&self, counter: usize, (id, walk, spike): (usize, f64, f64)) -> f64 {
+        // shift each instance so the curves do not overlap
+        let phase = (self.tick + id as u64 * 3) as f64 / PERIOD as f64;
+
+        match counter {
+            0 => 50.0 + 50.0 * (2.0 * PI * phase).sin(),
+            1 => 100.0 * ((phase * 2.0).floor() as u64 % 2) as f64,
+            2 => walk,
+            _ => spike,
+        }
+    }
+}
+
+impl CounterSource for Synthetic {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Synthetic" {
+            return None;
+        }
+
+        let counter = COUNTERS.iter().position(|name| *name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), counter));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn offers(&self) -> Vec<CounterPath> {
+        super::paths_of("Synthetic", COUNTERS.into_iter())
+    }
+
+    fn collect(&mut self, _elapsed: time::Duration) -> bool {
+        self.tick += 1;
+
+        if let Some(churn) = self.churn {
+            if self.tick.is_multiple_of(churn) && !self.instances.is_empty() {
+                self.instances.remove(0);
+                self.instances.push((self.next_instance, 50.0, 0.0));
+                self.next_instance += 1;
+            }
+        }
+
+        for index in 0..self.instances.len() {
+            let step = (self.random() - 0.5) * 10.0;
+            let spike = if self.random() < SPIKE_PROBABILITY {
+                100.0
+            } else {
+                self.random() * 5.0
+            };
+
+            let (_, walk, last_spike) = &mut self.instances[index];
+            *walk = (*walk + step).clamp(0.0, 100.0);
+            *last_spike = spike;
+        }
+
+        true
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, counter) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.instances
+                .iter()
+                .map(|instance| (instance.0.to_string(), self.value(*counter, *instance))),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_instances_churn_and_seed() {
+        let synthetic = Synthetic::parse("3, 10, 7").unwrap();
+        assert_eq!(synthetic.instances.len(), 3);
+        assert_eq!(synthetic.churn, Some(10));
+        assert_eq!(synthetic.seed, 7);
+
+        let synthetic = Synthetic::parse("2").unwrap();
+        assert_eq!(synthetic.churn, None);
+        assert_eq!(synthetic.seed, 1);
+
+        assert!(Synthetic::parse("0").is_none());
+        assert!(Synthetic::parse("two").is_none());
+    }
+}
@@ -0,0 +1,296 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use std::{
+    collections, fs,
+    io::{Read, Seek, SeekFrom},
+    path, time,
+};
+
+use super::{CounterPath, CounterSource};
+
+const VALUE_INSTANCES: [&str; 3] = ["min", "avg", "max"];
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    // matching lines per second, one instance per `name` group if any
+    #[default]
+    Count,
+    // min/avg/max of the `value` group, or the first group, over the interval
+    Value,
+}
+
+#[derive(Deserialize)]
+pub struct Definition {
+    pub name: String,
+    pub file: path::PathBuf,
+    pub regex: String,
+    #[serde(default)]
+    pub mode: Mode,
+}
+
+// follows a file by name like tail -F, reopening it when rotated or truncated
+struct Follower {
+    path: path::PathBuf,
+    file: Option<fs::File>,
+    id: Option<u64>,
+    offset: u64,
+    partial: Vec<u8>,
+    is_started: bool,
+}
+
+#[cfg(unix)]
+fn file_id(file: &fs::File) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(file.metadata().ok()?.ino())
+}
+
+// the file index, kept by a rename and new for a file created in its place
+#[cfg(windows)]
+fn file_id(file: &fs::File) -> Option<u64> {
+    use std::{mem, os::windows::io::AsRawHandle};
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    unsafe {
+        let mut information: BY_HANDLE_FILE_INFORMATION = mem::zeroed();
+        match GetFileInformationByHandle(file.as_raw_handle() as _, &mut information) {
+            0 => None,
+            _ => {
+                Some(((information.nFileIndexHigh as u64) << 32) | information.nFileIndexLow as u64)
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_id(_file: &fs::File) -> Option<u64> {
+    None
+}
+
+impl Follower {
+    fn new(path: path::PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            id: None,
+            offset: 0,
+            partial: Vec::new(),
+            is_started: false,
+        }
+    }
+
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut buffer = Vec::new();
+        // opened by name, the file in place now, which may not be the one followed
+        let current = fs::File::open(&self.path).ok();
+        let current_id = current.as_ref().and_then(file_id);
+
+        let is_replaced = match current.as_ref().and_then(|file| file.metadata().ok()) {
+            Some(metadata) => current_id != self.id || metadata.len() < self.offset,
+            None => true,
+        };
+
+        if is_replaced {
+            // the rest of a rotated file still belongs to this interval
+            if let Some(file) = self.file.as_mut() {
+                let _ = file.read_to_end(&mut buffer);
+            }
+
+            self.file = None;
+            self.offset = 0;
+        }
+
+        if self.file.is_none() {
+            if let Some(mut file) = current {
+                // like tail, lines written before the first open are not counted
+                if !self.is_started {
+                    self.offset = file.seek(SeekFrom::End(0)).unwrap_or(0);
+                }
+
+                self.id = current_id;
+                self.file = Some(file);
+            }
+            self.is_started = true;
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            if let Ok(len) = file.read_to_end(&mut buffer) {
+                self.offset += len as u64;
+            }
+        }
+
+        self.partial.extend(buffer);
+
+        // an unterminated line is kept until the writer finishes it
+        let end = match self.partial.iter().rposition(|byte| *byte == b'\n') {
+            Some(end) => end + 1,
+            None => return Vec::new(),
+        };
+
+        self.partial
+            .drain(..end)
+            .collect::<Vec<_>>()
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| String::from_utf8_lossy(line).trim_end().to_string())
+            .collect()
+    }
+}
+
+struct Log {
+    definition: Definition,
+    regex: Regex,
+    follower: Follower,
+    names: collections::BTreeSet<String>,
+}
+
+pub struct Tail {
+    logs: Vec<Log>,
+    curr: Vec<Option<Vec<(String, f64)>>>,
+    counters: collections::HashMap<usize, (Option<String>, usize)>,
+    last_id: usize,
+}
+
+impl Tail {
+    pub fn new(definitions: Vec<Definition>) -> Self {
+        let logs = definitions
+            .into_iter()
+            .filter_map(|definition| {
+                let regex = Regex::new(&definition.regex)
+                    .map_err(|err| eprintln!("Invalid regex for {} err({})", definition.name, err))
+                    .ok()?;
+
+                Some(Log {
+                    follower: Follower::new(definition.file.clone()),
+                    definition,
+                    regex,
+                    names: collections::BTreeSet::new(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            curr: logs.iter().map(|_| None).collect(),
+            logs,
+            counters: collections::HashMap::new(),
+            last_id: 0,
+        }
+    }
+}
+
+impl CounterSource for Tail {
+    fn add_counter(&mut self, path: &CounterPath) -> Option<usize> {
+        if path.object != "Log" {
+            return None;
+        }
+
+        let log = self
+            .logs
+            .iter()
+            .position(|log| log.definition.name == path.counter)?;
+
+        self.last_id += 1;
+        self.counters
+            .insert(self.last_id, (path.full_instance(), log));
+
+        Some(self.last_id)
+    }
+
+    fn remove_counter(&mut self, hcounter: usize) {
+        self.counters.remove(&hcounter);
+    }
+
+    fn offers(&self) -> Vec<CounterPath> {
+        super::paths_of(
+            "Log",
+            self.logs.iter().map(|log| log.definition.name.as_str()),
+        )
+    }
+
+    // every file is followed, so a counter added later doesn't see a backlog of lines
+    fn collect(&mut self, elapsed: time::Duration) -> bool {
+        for (log, curr) in self.logs.iter_mut().zip(self.curr.iter_mut()) {
+            let lines = log.follower.read_lines();
+            let captures = lines.iter().filter_map(|line| log.regex.captures(line));
+
+            *curr = match log.definition.mode {
+                Mode::Count => {
+                    let mut counts = collections::HashMap::<String, f64>::new();
+                    for captures in captures {
+                        let name = captures.name("name").map_or("", |name| name.as_str());
+                        *counts.entry(name.to_string()).or_insert(0.0) += 1.0;
+                    }
+
+                    super::per_second(&mut log.names, &counts, elapsed)
+                }
+                Mode::Value => {
+                    let values = captures
+                        .filter_map(|captures| {
+                            let value = captures.name("value").or_else(|| captures.get(1))?;
+                            value.as_str().trim().parse::<f64>().ok()
+                        })
+                        .collect::<Vec<_>>();
+
+                    let min = values.iter().copied().fold(f64::MAX, f64::min);
+                    let max = values.iter().copied().fold(f64::MIN, f64::max);
+                    let avg = values.iter().sum::<f64>() / values.len() as f64;
+
+                    (!values.is_empty()).then(|| {
+                        VALUE_INSTANCES
+                            .iter()
+                            .map(|instance| instance.to_string())
+                            .zip([min, avg, max])
+                            .collect()
+                    })
+                }
+            };
+        }
+
+        true
+    }
+
+    fn get_values(&self, hcounter: usize) -> Option<Vec<(String, f64)>> {
+        let (instance, log) = self.counters.get(&hcounter)?;
+
+        Some(super::filter_instances(
+            instance.as_deref(),
+            self.curr[*log].clone()?.into_iter(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, io::Write, process};
+
+    #[test]
+    fn rotated_file_is_followed_even_when_longer() {
+        let dir = env::temp_dir().join(format!("pdhv-tail-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        fs::write(&path, "").unwrap();
+
+        let mut follower = Follower::new(path.clone());
+        assert!(follower.read_lines().is_empty());
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "old").unwrap();
+        assert_eq!(follower.read_lines(), vec!["old"]);
+
+        // the new file is longer than what was read of the old one
+        fs::rename(&path, dir.join("test.log.1")).unwrap();
+        writeln!(file, "last").unwrap();
+        fs::write(&path, "a longer first line\nsecond\n").unwrap();
+        assert_eq!(
+            follower.read_lines(),
+            vec!["last", "a longer first line", "second"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use super::counter_path::CounterPath;
+
+const SI_PREFIXES: [&str; 7] = ["", "k", "M", "G", "T", "P", "E"];
+const IEC_PREFIXES: [&str; 7] = ["", "Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    #[default]
+    None,
+    Bytes,
+    #[serde(rename = "bytes/s")]
+    BytesPerSecond,
+    #[serde(rename = "%")]
+    Percent,
+    #[serde(rename = "/s")]
+    PerSecond,
+    Ms,
+    #[serde(rename = "ms/s")]
+    MsPerSecond,
+    #[serde(rename = "°C")]
+    Celsius,
+    Rpm,
+    #[serde(rename = "V")]
+    Volts,
+}
+
+// 3 significant digits, without trailing zeros
+fn round(value: f64) -> String {
+    let decimals = match value.abs() {
+        value if value >= 100.0 => 0,
+        value if value >= 10.0 => 1,
+        _ => 2,
+    };
+
+    let string = format!("{:.*}", decimals, value);
+    if string.contains('.') {
+        string
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        string
+    }
+}
+
+fn prefixed(value: f64, base: f64, prefixes: &[&str]) -> String {
+    let mut value = value;
+    let mut prefix = 0;
+
+    while value.abs() >= base && prefix < prefixes.len() - 1 {
+        value /= base;
+        prefix += 1;
+    }
+
+    // 999.7 rounds to 1000, show 1 k instead
+    let rounded = round(value).parse::<f64>().unwrap_or(value);
+    if rounded.abs() >= base && prefix < prefixes.len() - 1 {
+        value /= base;
+        prefix += 1;
+    }
+
+    format!("{} {}", round(value), prefixes[prefix])
+}
+
+impl Unit {
+    // from the usual counter names, "% Processor Time", "Disk Read Bytes/sec", ...
+    pub fn guess(path: &CounterPath) -> Self {
+        let counter = path.counter.as_str();
+
+        match () {
+            _ if counter.starts_with('%') => Self::Percent,
+            _ if counter.contains("Bytes/sec") => Self::BytesPerSecond,
+            _ if counter.contains("Bytes") => Self::Bytes,
+            _ if counter.starts_with("Working Set") || counter == "Commit Limit" => Self::Bytes,
+            // pressure stall, in milliseconds stalled per second
+            _ if counter.ends_with("Stall Time/sec") => Self::MsPerSecond,
+            _ if counter.ends_with("/sec") => Self::PerSecond,
+            _ if counter == "Temperature" => Self::Celsius,
+            _ if counter == "Fan Speed" => Self::Rpm,
+            _ if counter == "Voltage" => Self::Volts,
+            _ => Self::None,
+        }
+    }
+
+    // SI prefixes, or IEC ones for bytes: 340 k/s, 1.2 GiB
+    pub fn format(&self, value: f64) -> String {
+        let string = match self {
+            Self::Bytes | Self::BytesPerSecond => prefixed(value, 1024.0, &IEC_PREFIXES) + "B",
+            Self::Percent
+            | Self::Ms
+            | Self::MsPerSecond
+            | Self::Celsius
+            | Self::Rpm
+            | Self::Volts => round(value) + " ",
+            Self::None | Self::PerSecond => prefixed(value, 1000.0, &SI_PREFIXES),
+        };
+
+        let suffix = match self {
+            Self::None | Self::Bytes => "",
+            Self::BytesPerSecond | Self::PerSecond => "/s",
+            Self::Percent => "%",
+            Self::Ms => "ms",
+            Self::MsPerSecond => "ms/s",
+            Self::Celsius => "°C",
+            Self::Rpm => "RPM",
+            Self::Volts => "V",
+        };
+
+        (string + suffix).trim_end().to_string()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unit: Unit,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Metadata {
+    pub fn new(path: &CounterPath) -> Self {
+        Self {
+            name: None,
+            unit: Unit::guess(path),
+            scale: default_scale(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guess(path: &str) -> Unit {
+        Unit::guess(&CounterPath::parse(path).unwrap())
+    }
+
+    #[test]
+    fn stall_time_is_in_milliseconds_per_second() {
+        assert!(guess(r"\Pressure(cpu)\Some Stall Time/sec") == Unit::MsPerSecond);
+        assert!(guess(r"\Network Interface(*)\Packets/sec") == Unit::PerSecond);
+        assert_eq!(Unit::MsPerSecond.format(12.5), "12.5 ms/s");
+        assert_eq!(Unit::PerSecond.format(1500.0), "1.5 k/s");
+    }
+
+    #[test]
+    fn byte_counters_are_guessed() {
+        for path in [
+            r"\Memory\Available Bytes",
+            r"\Memory\Commit Limit",
+            r"\Process(*)\Working Set",
+            r"\Process(*)\Working Set - Private",
+        ] {
+            assert!(guess(path) == Unit::Bytes, "{}", path);
+        }
+        assert!(guess(r"\PhysicalDisk(*)\Disk Read Bytes/sec") == Unit::BytesPerSecond);
+        assert!(guess(r"\Processor(*)\% Processor Time") == Unit::Percent);
+        assert!(guess(r"\Process(*)\Thread Count") == Unit::None);
+    }
+
+    #[test]
+    fn values_are_formatted_with_prefixes() {
+        // IEC for bytes
+        assert_eq!(Unit::Bytes.format(512.0), "512 B");
+        assert_eq!(Unit::Bytes.format(1536.0), "1.5 KiB");
+        assert_eq!(Unit::Bytes.format(1288490188.8), "1.2 GiB");
+        assert_eq!(Unit::BytesPerSecond.format(1048576.0), "1 MiB/s");
+
+        // SI otherwise
+        assert_eq!(Unit::PerSecond.format(340000.0), "340 k/s");
+        assert_eq!(Unit::None.format(0.0), "0");
+        assert_eq!(Unit::None.format(999.4), "999");
+        assert_eq!(Unit::None.format(-1500.0), "-1.5 k");
+        assert_eq!(Unit::None.format(2.5e18), "2.5 E");
+
+        // rounding up to the next prefix
+        assert_eq!(Unit::None.format(999.7), "1 k");
+        assert_eq!(Unit::Bytes.format(1023.9), "1 KiB");
+
+        assert_eq!(Unit::Percent.format(12.345), "12.3 %");
+        assert_eq!(Unit::Celsius.format(45.0), "45 °C");
+    }
+}